@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use zeroize::Zeroizing;
+
+use crate::file;
+use crate::kdf;
+use crate::secret::Secret;
+
+pub const SOCKET_NAME: &str = "agent.sock";
+
+/// The agent listens on `~/.onepass/agent.sock`, only readable by the user.
+pub fn socket_path() -> PathBuf {
+    let mut path = file::path(Some(file::DEFAULT_DIR_NAME));
+    path.push(SOCKET_NAME);
+    path
+}
+
+struct Entry {
+    key: kdf::Key,
+    salt: Vec<u8>,
+    params: kdf::Params,
+    last_used: Instant,
+}
+
+/// Keys held by the agent, by the absolute path of the file they unlock.
+struct State {
+    entries: HashMap<String, Entry>,
+    timeout: Duration,
+    stopped: bool,
+}
+
+impl State {
+    fn new(timeout: Duration) -> State {
+        State {
+            entries: HashMap::new(),
+            timeout,
+            stopped: false,
+        }
+    }
+
+    /// Answer a single request line.
+    ///
+    /// ```text
+    /// ping                                  -> ok
+    /// get <path>                            -> key <salt> <m> <t> <p> <key> | none
+    /// put <salt> <m> <t> <p> <key> <path>   -> ok
+    /// forget <path>                         -> ok
+    /// lock                                  -> ok
+    /// stop                                  -> ok
+    /// ```
+    ///
+    /// Salts and keys are hex encoded, paths go last as they may contain spaces.
+    /// Responses may carry a key, so they are wiped once sent.
+    fn handle(&mut self, line: &str) -> Secret {
+        self.expire();
+
+        let (cmd, rest) = match line.split_once(' ') {
+            Some(v) => v,
+            None => (line, ""),
+        };
+        match cmd {
+            "ping" => Secret::from("ok"),
+            "get" => match self.entries.get_mut(rest) {
+                Some(entry) => {
+                    entry.last_used = Instant::now();
+                    Secret::new(format!(
+                        "key {} {} {} {} {}",
+                        hex_encode(&entry.salt).expose(),
+                        entry.params.m_cost,
+                        entry.params.t_cost,
+                        entry.params.p_cost,
+                        hex_encode(&entry.key[..]).expose()
+                    ))
+                }
+                None => Secret::from("none"),
+            },
+            "put" => {
+                let parts: Vec<&str> = rest.splitn(6, ' ').collect();
+                if parts.len() != 6 {
+                    return Secret::from("error malformed request");
+                }
+                let (salt, params, key) = match parse_key(&parts[..5]) {
+                    Some(v) => v,
+                    None => return Secret::from("error malformed key"),
+                };
+                self.entries.insert(
+                    parts[5].to_string(),
+                    Entry {
+                        key,
+                        salt,
+                        params,
+                        last_used: Instant::now(),
+                    },
+                );
+                Secret::from("ok")
+            }
+            "forget" => {
+                self.entries.remove(rest);
+                Secret::from("ok")
+            }
+            "lock" => {
+                self.entries.clear();
+                Secret::from("ok")
+            }
+            "stop" => {
+                self.entries.clear();
+                self.stopped = true;
+                Secret::from("ok")
+            }
+            _ => Secret::from("error unknown request"),
+        }
+    }
+
+    /// Drop keys that have not been used within the idle timeout.
+    fn expire(&mut self) {
+        let timeout = self.timeout;
+        self.entries.retain(|_, e| e.last_used.elapsed() < timeout);
+    }
+}
+
+fn parse_key(parts: &[&str]) -> Option<(Vec<u8>, kdf::Params, kdf::Key)> {
+    let salt = hex_decode(parts[0])?;
+    let params = kdf::Params {
+        m_cost: parts[1].parse().ok()?,
+        t_cost: parts[2].parse().ok()?,
+        p_cost: parts[3].parse().ok()?,
+    };
+    params.check().ok()?;
+    let bytes = Zeroizing::new(hex_decode(parts[4])?);
+    if bytes.len() != kdf::KEY_LEN {
+        return None;
+    }
+    let mut key = kdf::Key::zeroed();
+    key.copy_from_slice(&bytes);
+    Some((salt, params, key))
+}
+
+fn hex_encode(bytes: &[u8]) -> Secret {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push_str(&format!("{:02x}", b));
+    }
+    Secret::new(s)
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn vault_id(custom: Option<&str>) -> String {
+    file::path(custom).to_string_lossy().to_string()
+}
+
+fn request(line: &str) -> Option<Secret> {
+    let mut stream = UnixStream::connect(socket_path()).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    stream.write_all(line.as_bytes()).ok()?;
+    stream.write_all(b"\n").ok()?;
+
+    let mut response = Secret::default();
+    BufReader::new(stream)
+        .read_line(response.expose_mut())
+        .ok()?;
+    Some(Secret::from(response.expose().trim_end()))
+}
+
+pub fn running() -> bool {
+    request("ping").is_some_and(|v| v.expose() == "ok")
+}
+
+/// Get the cached key of a file, if the agent is running and holds one.
+pub fn get(custom: Option<&str>) -> Option<kdf::Credential> {
+    let response = request(&format!("get {}", vault_id(custom)))?;
+    let parts: Vec<&str> = response.expose().strip_prefix("key ")?.split(' ').collect();
+    if parts.len() != 5 {
+        return None;
+    }
+    let (salt, params, key) = parse_key(&parts)?;
+    Some(kdf::Credential::Key(kdf::DerivedKey { salt, params, key }))
+}
+
+/// Hand a derived key to the agent. Passwords are never sent.
+pub fn put(custom: Option<&str>, credential: &kdf::Credential) {
+    if let kdf::Credential::Key(k) = credential {
+        let line = Secret::new(format!(
+            "put {} {} {} {} {} {}",
+            hex_encode(&k.salt).expose(),
+            k.params.m_cost,
+            k.params.t_cost,
+            k.params.p_cost,
+            hex_encode(&k.key[..]).expose(),
+            vault_id(custom)
+        ));
+        request(line.expose());
+    }
+}
+
+pub fn forget(custom: Option<&str>) {
+    request(&format!("forget {}", vault_id(custom)));
+}
+
+/// Wipe every key held by the agent.
+pub fn lock() -> Result<(), String> {
+    match request("lock").as_ref().map(|v| v.expose()) {
+        Some("ok") => Ok(()),
+        _ => Err("agent is not running".to_string()),
+    }
+}
+
+pub fn stop() -> Result<(), String> {
+    match request("stop").as_ref().map(|v| v.expose()) {
+        Some("ok") => Ok(()),
+        _ => Err("agent is not running".to_string()),
+    }
+}
+
+/// Start the agent in the background, detached from the terminal.
+pub fn start(timeout: Duration) -> Result<(), String> {
+    if running() {
+        return Err("agent is already running".to_string());
+    }
+    let exe = match std::env::current_exe() {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let mut cmd = Command::new(exe);
+    cmd.arg("agent")
+        .arg("--foreground")
+        .arg("--timeout")
+        .arg(timeout.as_secs().to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe.
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    if let Err(err) = cmd.spawn() {
+        return Err(err.to_string());
+    }
+
+    for _ in 0..50 {
+        if running() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Err("agent did not start".to_string())
+}
+
+/// Run the agent in the foreground until it is stopped.
+pub fn serve(timeout: Duration) -> Result<(), String> {
+    let path = socket_path();
+    if running() {
+        return Err("agent is already running".to_string());
+    }
+    if let Some(dir) = path.parent() {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            return Err(err.to_string());
+        }
+    }
+    // A socket left behind by an agent that did not shut down cleanly.
+    let _ = std::fs::remove_file(&path);
+
+    // SAFETY: umask only changes the file mode creation mask.
+    let old_mask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&path);
+    unsafe { libc::umask(old_mask) };
+    let listener = match listener {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+    if let Err(err) = listener.set_nonblocking(true) {
+        return Err(err.to_string());
+    }
+
+    let mut state = State::new(timeout);
+    while !state.stopped {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // A misbehaving client must not take the agent down.
+                let _ = handle_client(stream, &mut state);
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Err(err) => return Err(err.to_string()),
+        }
+        state.expire();
+    }
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+fn handle_client(stream: UnixStream, state: &mut State) -> io::Result<()> {
+    if !same_user(&stream) {
+        return Ok(());
+    }
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+    let mut line = Secret::default();
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(line.expose_mut())?;
+    let response = state.handle(line.expose().trim_end());
+
+    let mut writer = &stream;
+    writer.write_all(response.expose().as_bytes())?;
+    writer.write_all(b"\n")
+}
+
+/// Only serve processes of the user that runs the agent.
+#[cfg(target_os = "linux")]
+fn same_user(stream: &UnixStream) -> bool {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes of the given size.
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    // SAFETY: getuid can not fail.
+    res == 0 && cred.uid == unsafe { libc::getuid() }
+}
+
+/// Only serve processes of the user that runs the agent.
+#[cfg(not(target_os = "linux"))]
+fn same_user(stream: &UnixStream) -> bool {
+    let mut uid = 0;
+    let mut gid = 0;
+    // SAFETY: `uid` and `gid` are valid out pointers.
+    let res = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    // SAFETY: getuid can not fail.
+    res == 0 && uid == unsafe { libc::getuid() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle() {
+        let mut state = State::new(Duration::from_secs(60));
+        assert_eq!("ok", state.handle("ping").expose());
+        assert_eq!(
+            "none",
+            state.handle("get /home/user/.onepass/main.txt").expose()
+        );
+
+        let key = format!("key 0a0b 1024 2 1 {}", "ff".repeat(kdf::KEY_LEN));
+        let put = format!("put {} /home/user/my vault.txt", &key[4..]);
+        assert_eq!("ok", state.handle(&put).expose());
+        assert_eq!(key, state.handle("get /home/user/my vault.txt").expose());
+
+        assert_eq!(
+            "ok",
+            state.handle("forget /home/user/my vault.txt").expose()
+        );
+        assert_eq!("none", state.handle("get /home/user/my vault.txt").expose());
+
+        assert_eq!("ok", state.handle(&put).expose());
+        assert_eq!("ok", state.handle("lock").expose());
+        assert_eq!("none", state.handle("get /home/user/my vault.txt").expose());
+
+        assert_eq!(
+            "error malformed key",
+            state.handle("put zz 1 1 1 ff /path").expose()
+        );
+        let oversized = format!("put 0a 4294967295 2 1 {} /path", "ff".repeat(kdf::KEY_LEN));
+        assert_eq!("error malformed key", state.handle(&oversized).expose());
+        assert_eq!(
+            "error malformed request",
+            state.handle("put 0a 1 1").expose()
+        );
+        assert_eq!("error unknown request", state.handle("nope").expose());
+
+        assert!(!state.stopped);
+        assert_eq!("ok", state.handle("stop").expose());
+        assert!(state.stopped);
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut state = State::new(Duration::from_millis(50));
+        let put = format!("put 0a 1024 2 1 {} /vault", "ff".repeat(kdf::KEY_LEN));
+        assert_eq!("ok", state.handle(&put).expose());
+        assert!(state.handle("get /vault").expose().starts_with("key "));
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert_eq!("none", state.handle("get /vault").expose());
+    }
+
+    #[test]
+    fn test_hex() {
+        let bytes = vec![0u8, 1, 171, 255];
+        assert_eq!("0001abff", hex_encode(&bytes).expose());
+        assert_eq!(Some(bytes), hex_decode("0001abff"));
+        assert_eq!(None, hex_decode("abc"));
+        assert_eq!(None, hex_decode("zz"));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::resource::Instance;
+use crate::strength;
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// The same password protects several resources.
+    Reused,
+    /// The password is easy to guess.
+    Weak,
+    /// The password contains the user or resource name.
+    Personal,
+    /// The password was last changed longer ago than the threshold.
+    Old,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Reused => "reused",
+            Kind::Weak => "weak",
+            Kind::Personal => "personal",
+            Kind::Old => "old",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub kind: Kind,
+    pub resources: Vec<String>,
+    pub message: String,
+}
+
+/// Check the passwords of `resources`, most severe findings first.
+/// Resources without a password are skipped, as are ages when
+/// `max_age_days` is 0 or the time the password was set is unknown.
+pub fn audit(resources: &[Instance], now: u64, max_age_days: u64) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut by_password: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+    for v in resources.iter().filter(|v| !v.password.is_empty()) {
+        let password = v.password.expose();
+        by_password
+            .entry(password)
+            .or_default()
+            .push(v.name.clone());
+
+        let estimate = strength::estimate(password);
+        let severity = match estimate.score {
+            0 | 1 => Some(Severity::High),
+            2 => Some(Severity::Medium),
+            _ => None,
+        };
+        if let Some(severity) = severity {
+            findings.push(Finding {
+                severity,
+                kind: Kind::Weak,
+                resources: vec![v.name.clone()],
+                message: format!(
+                    "guessable in about 10^{:.0} tries (score {}/4)",
+                    estimate.guesses_log10, estimate.score
+                ),
+            });
+        }
+
+        if let Some(what) = personal(v) {
+            findings.push(Finding {
+                severity: Severity::High,
+                kind: Kind::Personal,
+                resources: vec![v.name.clone()],
+                message: format!("password contains the {}", what),
+            });
+        }
+
+        let age = now.saturating_sub(v.changed) / DAY;
+        if max_age_days > 0 && v.changed > 0 && age > max_age_days {
+            findings.push(Finding {
+                severity: Severity::Low,
+                kind: Kind::Old,
+                resources: vec![v.name.clone()],
+                message: format!("password unchanged for {} days", age),
+            });
+        }
+    }
+
+    for names in by_password.into_values().filter(|v| v.len() > 1) {
+        findings.push(Finding {
+            severity: Severity::High,
+            kind: Kind::Reused,
+            message: format!("same password on {} resources", names.len()),
+            resources: names,
+        });
+    }
+
+    findings.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then(a.kind.cmp(&b.kind))
+            .then(a.resources.cmp(&b.resources))
+    });
+    findings
+}
+
+/// Which name the password contains, ignoring case. The part of an email
+/// address before the `@` counts as the user name.
+fn personal(resource: &Instance) -> Option<&'static str> {
+    let password = resource.password.expose().to_lowercase();
+    let contains = |v: &str| v.chars().count() >= 3 && password.contains(&v.to_lowercase());
+
+    let user = resource.user.split('@').next().unwrap_or_default();
+    if contains(&resource.user) || contains(user) {
+        return Some("user name");
+    }
+    if contains(&resource.name) {
+        return Some("resource name");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::Secret;
+
+    fn resource(name: &str, user: &str, password: &str, changed: u64) -> Instance {
+        Instance {
+            name: name.to_string(),
+            user: user.to_string(),
+            password: Secret::from(password),
+            changed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_audit() {
+        let now = 1_000 * DAY;
+        let resources = vec![
+            resource("github", "octocat", "v9#Lq2xT!mR4pZ8w", now),
+            resource("gitlab", "octocat", "v9#Lq2xT!mR4pZ8w", now),
+            resource("mail", "jane.doe@mail.test", "Jane.Doe-7w#Kq9", now),
+            resource("bank", "me", "P@ssw0rd", now),
+            resource("router", "admin", "Uj4$kq8!Zx2m#Pw7", now - 400 * DAY),
+            resource("notes", "", "", 0),
+            resource("forum", "me", "hQ7!vN3@xK9#cT2m", 0),
+        ];
+
+        let findings = audit(&resources, now, 365);
+        let got: Vec<(Severity, Kind, Vec<&str>)> = findings
+            .iter()
+            .map(|v| {
+                let names = v.resources.iter().map(|v| v.as_str()).collect();
+                (v.severity, v.kind, names)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Severity::High, Kind::Reused, vec!["github", "gitlab"]),
+                (Severity::High, Kind::Weak, vec!["bank"]),
+                (Severity::High, Kind::Personal, vec!["mail"]),
+                (Severity::Low, Kind::Old, vec!["router"]),
+            ],
+            got
+        );
+
+        assert!(audit(&resources, now, 0)
+            .iter()
+            .all(|v| v.kind != Kind::Old));
+    }
+}
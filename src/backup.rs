@@ -0,0 +1,258 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::file;
+
+pub const DIR_NAME: &str = "backups";
+
+/// A previous version of a vault, identified by the time it was replaced
+/// in milliseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub id: u128,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}  {}  {} bytes",
+            self.id,
+            format_timestamp(self.id),
+            self.size
+        )
+    }
+}
+
+/// Backups live in a `backups` directory next to the vault,
+/// e.g `~/.onepass/backups/main.txt.1729252800000`.
+pub fn dir(custom: Option<&str>) -> PathBuf {
+    let path = file::path(custom);
+    match path.parent() {
+        Some(v) => v.join(DIR_NAME),
+        None => PathBuf::from(DIR_NAME),
+    }
+}
+
+fn prefix(custom: Option<&str>) -> String {
+    let path = file::path(custom);
+    match path.file_name() {
+        Some(v) => format!("{}.", v.to_string_lossy()),
+        None => String::new(),
+    }
+}
+
+/// Copy the current vault into the backups directory and drop the oldest
+/// backups beyond `retention`. Empty or missing vaults are not backed up.
+pub fn create(custom: Option<&str>, retention: usize) -> io::Result<Option<Entry>> {
+    if retention == 0 {
+        return Ok(None);
+    }
+    let bytes = match std::fs::read(file::path(custom)) {
+        Ok(v) => v,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    let dir = dir(custom);
+    std::fs::create_dir_all(&dir)?;
+
+    let mut id = now();
+    let mut path = dir.join(format!("{}{}", prefix(custom), id));
+    while path.exists() {
+        id += 1;
+        path = dir.join(format!("{}{}", prefix(custom), id));
+    }
+    file::write_atomic(Some(&path.to_string_lossy()), &bytes)?;
+
+    prune(custom, retention)?;
+
+    Ok(Some(Entry {
+        id,
+        path,
+        size: bytes.len() as u64,
+    }))
+}
+
+/// List the backups of a vault, oldest first.
+pub fn list(custom: Option<&str>) -> io::Result<Vec<Entry>> {
+    let read_dir = match std::fs::read_dir(dir(custom)) {
+        Ok(v) => v,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let prefix = prefix(custom);
+    let mut result: Vec<Entry> = vec![];
+    for entry in read_dir {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let id = match name.strip_prefix(&prefix).map(|v| v.parse::<u128>()) {
+            Some(Ok(v)) => v,
+            _ => continue,
+        };
+        result.push(Entry {
+            id,
+            path: entry.path(),
+            size: entry.metadata()?.len(),
+        });
+    }
+    result.sort_by_key(|e| e.id);
+
+    Ok(result)
+}
+
+pub fn get(custom: Option<&str>, id: &str) -> Result<Entry, String> {
+    let list = match list(custom) {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+    for entry in list {
+        if entry.id.to_string() == id {
+            return Ok(entry);
+        }
+    }
+    Err("Backup not found".to_string())
+}
+
+/// Replace the vault with a backup. The current vault is backed up first,
+/// so a restore can be undone like any other write.
+pub fn restore(custom: Option<&str>, entry: &Entry, retention: usize) -> io::Result<()> {
+    let bytes = std::fs::read(&entry.path)?;
+    create(custom, retention)?;
+    file::write_atomic(custom, &bytes)
+}
+
+fn prune(custom: Option<&str>, retention: usize) -> io::Result<()> {
+    let list = list(custom)?;
+    if list.len() <= retention {
+        return Ok(());
+    }
+    for entry in &list[..list.len() - retention] {
+        std::fs::remove_file(&entry.path)?;
+    }
+    Ok(())
+}
+
+fn now() -> u128 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(v) => v.as_millis(),
+        Err(_) => 0,
+    }
+}
+
+/// Format milliseconds since the Unix epoch as a UTC date and time.
+pub fn format_timestamp(millis: u128) -> String {
+    let secs = (millis / 1000) as i64;
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    struct Cleanup {
+        file_name: String,
+    }
+
+    impl Cleanup {
+        fn path(&self) -> String {
+            format!("{}/{}.txt", file::DEFAULT_DIR_NAME, self.file_name)
+                .as_str()
+                .to_string()
+        }
+    }
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            let file_path = format!("{}/{}.txt", file::DEFAULT_DIR_NAME, self.file_name);
+            for entry in list(Some(&file_path)).expect("listing backups") {
+                std::fs::remove_file(entry.path).expect("cleaning up backup");
+            }
+            file::purge(Some(&file_path)).expect("cleaning up");
+        }
+    }
+
+    #[test]
+    fn test_create_and_prune() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+
+        assert_eq!(None, create(Some(t_path), 3).expect("backing up missing"));
+        file::create(Some(t_path)).expect("creating");
+        assert_eq!(None, create(Some(t_path), 3).expect("backing up empty"));
+
+        for i in 0..5 {
+            file::write_atomic(Some(t_path), format!("version{}", i).as_bytes()).expect("writing");
+            create(Some(t_path), 3).expect("backing up");
+        }
+
+        let list = list(Some(t_path)).expect("listing");
+        assert_eq!(3, list.len());
+        let contents: Vec<String> = list
+            .iter()
+            .map(|e| std::fs::read_to_string(&e.path).expect("reading"))
+            .collect();
+        assert_eq!(vec!["version2", "version3", "version4"], contents);
+
+        let last = &list[2];
+        assert_eq!(
+            last,
+            &get(Some(t_path), &last.id.to_string()).expect("getting")
+        );
+        assert_eq!("Backup not found", get(Some(t_path), "1").unwrap_err());
+    }
+
+    #[test]
+    fn test_create_disabled() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        file::create(Some(t_path)).expect("creating");
+        file::write_atomic(Some(t_path), b"content").expect("writing");
+
+        assert_eq!(None, create(Some(t_path), 0).expect("backing up"));
+        assert!(list(Some(t_path)).expect("listing").is_empty());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!("1970-01-01 00:00:00 UTC", format_timestamp(0));
+        assert_eq!("2000-02-29 12:34:56 UTC", format_timestamp(951827696000));
+        assert_eq!("2024-10-18 13:20:00 UTC", format_timestamp(1729257600000));
+    }
+}
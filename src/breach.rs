@@ -0,0 +1,201 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+/// Length of the hash prefix that names a file in the range layout.
+const PREFIX_LEN: usize = 5;
+
+/// A local copy of the Have I Been Pwned password hashes, so passwords can
+/// be checked without a network connection.
+pub enum Db {
+    /// One file of `SHA1:COUNT` lines sorted by hash, as the "ordered by
+    /// hash" download. Looked up by binary search, without reading it all.
+    Sorted(PathBuf),
+    /// A directory of files named by the first 5 characters of the hash,
+    /// holding `SUFFIX:COUNT` lines, as the range API serves them.
+    Ranges(PathBuf),
+}
+
+impl Db {
+    /// A directory is read as range files, anything else as a sorted dump.
+    pub fn open(path: &str) -> Result<Db, String> {
+        match std::fs::metadata(path) {
+            Ok(v) if v.is_dir() => Ok(Db::Ranges(PathBuf::from(path))),
+            Ok(_) => Ok(Db::Sorted(PathBuf::from(path))),
+            Err(err) => Err(format!("{}: {}", path, err)),
+        }
+    }
+
+    /// How often the password appears in breaches, 0 if it does not.
+    pub fn count(&self, password: &str) -> Result<u64, String> {
+        let hash = hash(password);
+        let result = match self {
+            Db::Sorted(path) => search_sorted(path, &hash),
+            Db::Ranges(path) => search_range(path, &hash),
+        };
+        result.map_err(|err| err.to_string())
+    }
+}
+
+/// The uppercase hex SHA-1 of the password, as the datasets hold it.
+fn hash(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
+
+/// Split a `HASH:COUNT` line. Lists without counts count every hash once.
+fn parse_line(line: &str) -> (&str, u64) {
+    let line = line.trim_end();
+    match line.split_once(':') {
+        Some((hash, count)) => (hash, count.trim().parse().unwrap_or(1)),
+        None => (line, 1),
+    }
+}
+
+fn search_range(dir: &Path, hash: &str) -> std::io::Result<u64> {
+    let (prefix, suffix) = hash.split_at(PREFIX_LEN);
+    let mut path = dir.join(prefix);
+    if !path.exists() {
+        path.set_extension("txt");
+    }
+    let file = match File::open(&path) {
+        Ok(v) => v,
+        // A complete dataset has every prefix, so a missing file is an error.
+        Err(err) => {
+            let msg = format!("{}: {}", path.display(), err);
+            return Err(std::io::Error::new(err.kind(), msg));
+        }
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let (v, count) = parse_line(&line);
+        if v.eq_ignore_ascii_case(suffix) {
+            return Ok(count);
+        }
+    }
+    Ok(0)
+}
+
+fn search_sorted(path: &Path, hash: &str) -> std::io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let len = reader.get_ref().metadata()?.len();
+
+    // Any line holding the hash starts in [lo, hi).
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let (start, line) = match line_from(&mut reader, mid)? {
+            Some(v) => v,
+            None => {
+                hi = mid;
+                continue;
+            }
+        };
+        if start >= hi {
+            hi = mid;
+            continue;
+        }
+        let (v, count) = parse_line(&line);
+        match v.to_ascii_uppercase().as_str().cmp(hash) {
+            Ordering::Equal => return Ok(count),
+            Ordering::Less => lo = start + line.len() as u64,
+            Ordering::Greater => hi = mid,
+        }
+    }
+    Ok(0)
+}
+
+/// The first line starting at or after `offset` with its start, including
+/// its line break. None past the last line.
+fn line_from(reader: &mut BufReader<File>, offset: u64) -> std::io::Result<Option<(u64, String)>> {
+    let mut start = offset;
+    let mut line = String::new();
+    if offset > 0 {
+        // Skip the rest of the line `offset` falls into, unless it starts one.
+        reader.seek(SeekFrom::Start(offset - 1))?;
+        start = offset - 1 + reader.read_line(&mut line)? as u64;
+        line.clear();
+    } else {
+        reader.seek(SeekFrom::Start(0))?;
+    }
+    match reader.read_line(&mut line)? {
+        0 => Ok(None),
+        _ => Ok(Some((start, line))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    struct Cleanup(PathBuf);
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sample() -> Vec<(String, u64)> {
+        let mut hashes: Vec<(String, u64)> = ["password", "123456", "letmein", "hunter2"]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (hash(v), i as u64 + 10))
+            .collect();
+        // Neighbours of the interesting hashes, to exercise the search.
+        for i in 0..200 {
+            hashes.push((hash(&format!("filler{}", i)), 1));
+        }
+        hashes.sort();
+        hashes
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", hash("password"));
+    }
+
+    #[test]
+    fn test_sorted() {
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let _cleanup = Cleanup(dir.clone());
+        std::fs::create_dir(&dir).expect("creating dir");
+        let path = dir.join("pwned.txt");
+        let content: String = sample()
+            .iter()
+            .map(|(h, c)| format!("{}:{}\r\n", h, c))
+            .collect();
+        std::fs::write(&path, content).expect("writing");
+
+        let db = Db::open(&path.to_string_lossy()).expect("opening");
+        assert_eq!(10, db.count("password").expect("checking"));
+        assert_eq!(13, db.count("hunter2").expect("checking"));
+        for i in 0..200 {
+            assert_eq!(1, db.count(&format!("filler{}", i)).expect("checking"));
+        }
+        assert_eq!(0, db.count("v9#Lq2xT!mR4pZ8w").expect("checking"));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let _cleanup = Cleanup(dir.clone());
+        std::fs::create_dir(&dir).expect("creating dir");
+        for (h, c) in sample() {
+            let (prefix, suffix) = h.split_at(PREFIX_LEN);
+            let mut content = std::fs::read_to_string(dir.join(prefix)).unwrap_or_default();
+            content.push_str(&format!("{}:{}\n", suffix, c));
+            std::fs::write(dir.join(prefix), content).expect("writing");
+        }
+
+        let db = Db::open(&dir.to_string_lossy()).expect("opening");
+        assert_eq!(11, db.count("123456").expect("checking"));
+        assert!(db.count("v9#Lq2xT!mR4pZ8w").is_err());
+    }
+}
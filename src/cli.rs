@@ -0,0 +1,645 @@
+use std::ffi::OsStr;
+use std::io::Write;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::{ArgValueCompleter, CompletionCandidate};
+
+use crate::audit;
+use crate::clipboard;
+use crate::command;
+use crate::export;
+use crate::harden;
+use crate::import;
+use crate::password;
+
+/// Light, ergonomic and portable terminal password manager.
+#[derive(Debug, Parser)]
+#[command(name = "onepass", version)]
+pub struct Cli {
+    /// Location of the file, relative to your home directory
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub location: Option<String>,
+
+    /// Allow core dumps, debuggers and swapping keys, e.g for debugging
+    #[arg(long, global = true)]
+    pub no_harden: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create a new resource
+    New {
+        #[command(flatten)]
+        generator: GeneratorOptions,
+    },
+    /// Get a resource by its name and copy its password
+    Get {
+        /// Name of the resource
+        #[arg(add = ArgValueCompleter::new(complete_names))]
+        name: String,
+        /// Seconds until the clipboard is cleared, 0 keeps the password until replaced
+        #[arg(short = 'c', long, value_name = "SECONDS")]
+        clear_after: Option<u64>,
+        /// Also print the passwords it had before
+        #[arg(long)]
+        history: bool,
+    },
+    /// Copy the current one-time code of a resource
+    Otp {
+        /// Name of the resource
+        #[arg(add = ArgValueCompleter::new(complete_names))]
+        name: String,
+        /// Seconds until the clipboard is cleared, 0 keeps the code until replaced
+        #[arg(short = 'c', long, value_name = "SECONDS")]
+        clear_after: Option<u64>,
+    },
+    /// Find resources by a fuzzy match on their name, tags, URL or username
+    Find {
+        /// What to look for, e.g part of a name
+        query: String,
+    },
+    /// Replace the password of a resource with a generated one
+    ///
+    /// The old password is kept in its history. Without options it is made
+    /// the way the resource's password was made before, options given are
+    /// remembered for the next rotation.
+    Rotate {
+        /// Name of the resource
+        #[arg(add = ArgValueCompleter::new(complete_names))]
+        name: String,
+        /// Seconds until the clipboard is cleared, 0 keeps the password until replaced
+        #[arg(short = 'c', long, value_name = "SECONDS")]
+        clear_after: Option<u64>,
+        #[command(flatten)]
+        generator: GeneratorOptions,
+    },
+    /// Delete a resource
+    Del {
+        /// Name of the resource
+        #[arg(add = ArgValueCompleter::new(complete_names))]
+        name: String,
+    },
+    /// List resources
+    List {
+        /// Only resources with this tag
+        #[arg(short, long)]
+        tag: Option<String>,
+        /// Only resources whose URL contains this
+        #[arg(short, long)]
+        url: Option<String>,
+    },
+    /// Update a resource - its name, username, password, url, notes, tags, totp secret or fields
+    Update {
+        /// Name of the resource
+        #[arg(add = ArgValueCompleter::new(complete_names))]
+        name: String,
+    },
+    /// Delete the file with all resources
+    Purge,
+    /// List backups or restore one
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+    /// Report reused, weak, personal and old passwords
+    Audit {
+        /// Print the findings as JSON
+        #[arg(long)]
+        json: bool,
+        /// Fail if there are findings of this severity or higher
+        #[arg(long, value_enum, value_name = "SEVERITY")]
+        fail_on: Option<audit::Severity>,
+        /// Days after which a password counts as old, 0 skips the check
+        #[arg(long, value_name = "DAYS", default_value_t = 365)]
+        max_age: u64,
+    },
+    /// Check all passwords against a local copy of Have I Been Pwned's password hashes
+    BreachCheck {
+        /// Hashes sorted in one file, or a directory of range files; `breach_db` in the config if not given
+        #[arg(long, value_name = "PATH")]
+        db: Option<String>,
+    },
+    /// Import the resources of another password manager's export
+    Import {
+        /// Which password manager the export comes from
+        #[arg(short, long)]
+        format: import::Format,
+        /// The exported file
+        file: String,
+        /// What to do with resources whose name is already taken
+        #[arg(short, long, value_enum, default_value_t = import::Duplicates::Skip)]
+        duplicates: import::Duplicates,
+        /// Only report what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export all resources, e.g to move them to another machine
+    Export {
+        /// What to write
+        #[arg(short, long, value_enum, default_value_t = export::Format::Archive)]
+        format: export::Format,
+        /// Where to write the export, stdout if not given
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+        /// Confirm that the export holds your passwords unencrypted
+        #[arg(long)]
+        plaintext: bool,
+    },
+    /// Browse, copy and edit resources in a full-screen interface
+    Tui {
+        /// Seconds without input until it locks, 0 never locks
+        #[arg(long, value_name = "SECONDS")]
+        lock_after: Option<u64>,
+    },
+    /// Run commands at a prompt, unlocking the file only once
+    Shell {
+        /// Seconds without input until it locks, 0 never locks
+        #[arg(long, value_name = "SECONDS")]
+        lock_after: Option<u64>,
+    },
+    /// Change the master password
+    Passwd,
+    /// Keep unlocked files unlocked while in use
+    Agent {
+        #[command(subcommand)]
+        action: Option<AgentAction>,
+        /// Seconds an unused key is kept
+        #[arg(short, long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        /// Serve in this process instead of starting a background agent
+        #[arg(long, hide = true)]
+        foreground: bool,
+    },
+    /// Make the agent forget all unlocked files
+    Lock,
+    /// Show which protections against leaking secrets are active
+    Doctor,
+    /// Suggest a new strong password
+    Suggest {
+        #[command(flatten)]
+        generator: GeneratorOptions,
+    },
+    /// Print a shell completion script
+    Completions {
+        shell: Shell,
+        /// Complete resource names too, by calling onepass on every <TAB>.
+        /// Names are only offered while the agent holds the key
+        #[arg(long)]
+        dynamic: bool,
+    },
+    /// Holds a copied value on the clipboard, see `clipboard::copy`
+    #[command(name = clipboard::HELPER_COMMAND, hide = true)]
+    ClipboardHelper { timeout: u64 },
+}
+
+/// Options of generated passwords, e.g for sites that reject some symbols.
+#[derive(Debug, Args)]
+pub struct GeneratorOptions {
+    /// Number of characters
+    #[arg(long, value_name = "N", default_value_t = password::DEFAULT_LENGTH)]
+    pub length: usize,
+    /// Only letters and digits
+    #[arg(long, conflicts_with = "symbols")]
+    pub no_symbols: bool,
+    /// Symbols to use instead of the default ones
+    #[arg(long, value_name = "CHARS")]
+    pub symbols: Option<String>,
+    /// Leave out characters that are easy to mix up: 0, O, 1, l, I and |
+    #[arg(long)]
+    pub exclude_ambiguous: bool,
+    /// Least number of digits
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub min_digits: usize,
+    /// Characters never to use
+    #[arg(long, value_name = "CHARS")]
+    pub exclude: Option<String>,
+
+    /// Words instead of characters, easier to remember and type
+    #[arg(
+        long,
+        conflicts_with_all = ["length", "no_symbols", "symbols", "exclude_ambiguous", "min_digits", "exclude"]
+    )]
+    pub passphrase: bool,
+    /// Number of words of a passphrase
+    #[arg(long, value_name = "N", requires = "passphrase", default_value_t = password::DEFAULT_WORDS)]
+    pub words: usize,
+    /// Put between the words of a passphrase
+    #[arg(long, value_name = "TEXT", requires = "passphrase", default_value = password::DEFAULT_SEPARATOR)]
+    pub separator: String,
+    /// Start every word of a passphrase with an uppercase letter
+    #[arg(long, requires = "passphrase")]
+    pub capitalize: bool,
+    /// Add a digit to one word of a passphrase
+    #[arg(long, requires = "passphrase")]
+    pub with_number: bool,
+
+    /// Whether any of the options was given, see `GeneratorOptions::given`.
+    #[arg(skip)]
+    pub given: bool,
+}
+
+impl GeneratorOptions {
+    pub fn generator(&self) -> password::Generator {
+        if self.passphrase {
+            return password::Generator::Passphrase(password::Passphrase {
+                words: self.words,
+                separator: self.separator.clone(),
+                capitalize: self.capitalize,
+                with_number: self.with_number,
+            });
+        }
+        let default = password::Policy::default();
+        password::Generator::Password(password::Policy {
+            length: self.length,
+            symbols: match (&self.symbols, self.no_symbols) {
+                (_, true) => String::new(),
+                (Some(v), false) => v.clone(),
+                (None, false) => default.symbols,
+            },
+            exclude_ambiguous: self.exclude_ambiguous,
+            min_digits: self.min_digits,
+            exclude: self.exclude.clone().unwrap_or_default(),
+        })
+    }
+
+    /// The generator, if any of the options was given. Giving the default
+    /// values still counts, e.g to go back to the default policy.
+    pub fn custom(&self) -> Option<password::Generator> {
+        self.given.then(|| self.generator())
+    }
+
+    /// Whether any of the options was given on the command line, as their
+    /// defaults can not be told apart from the values once parsed.
+    fn given(matches: &ArgMatches) -> bool {
+        GeneratorOptions::augment_args(clap::Command::new("generator"))
+            .get_arguments()
+            .any(|v| matches.value_source(v.get_id().as_str()) == Some(ValueSource::CommandLine))
+    }
+}
+
+/// Parses the generator options of a command in `onepass shell`.
+#[derive(Debug, Parser)]
+#[command(no_binary_name = true)]
+struct GeneratorLine {
+    #[command(flatten)]
+    options: GeneratorOptions,
+}
+
+/// Generator options given as one line, e.g `--length 20 --symbols '!#'`.
+/// Quoted the way a shell quotes them.
+pub fn parse_generator(line: &str) -> Result<GeneratorOptions, String> {
+    let args = match shlex::split(line) {
+        Some(v) => v,
+        None => return Err("unfinished quote or escape in the options".to_string()),
+    };
+    let matches = match GeneratorLine::command().try_get_matches_from(args) {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string().trim_end().to_string()),
+    };
+    match GeneratorLine::from_arg_matches(&matches) {
+        Ok(mut v) => {
+            v.options.given = GeneratorOptions::given(&matches);
+            Ok(v.options)
+        }
+        Err(err) => Err(err.to_string().trim_end().to_string()),
+    }
+}
+
+/// Parse the arguments of the process, exiting on errors like
+/// `Cli::parse` does.
+pub fn parse() -> Cli {
+    let mut command = Cli::command();
+    let result = command
+        .try_get_matches_from_mut(std::env::args_os())
+        .and_then(|v| from_matches(&v));
+    match result {
+        Ok(v) => v,
+        Err(err) => err.format(&mut command).exit(),
+    }
+}
+
+/// Unlike the derived parsing, notes which generator options were given.
+fn from_matches(matches: &ArgMatches) -> Result<Cli, clap::Error> {
+    let mut cli = Cli::from_arg_matches(matches)?;
+    if let (Command::Rotate { generator, .. }, Some((_, v))) =
+        (&mut cli.command, matches.subcommand())
+    {
+        generator.given = GeneratorOptions::given(v);
+    }
+    Ok(cli)
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupAction {
+    /// List the backups of the file, oldest first
+    List,
+    /// Replace the file with one of its backups
+    Restore {
+        /// ID as shown by `onepass backup list`
+        id: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AgentAction {
+    /// Stop the agent, forgetting all keys
+    Stop,
+}
+
+/// Environment variable the dynamic completion scripts call onepass with.
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Write the completion script for the shell. The static one only knows
+/// commands and flags, the dynamic one asks onepass on every completion.
+pub fn write_completions(shell: Shell, dynamic: bool, buf: &mut dyn Write) -> Result<(), String> {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    if !dynamic {
+        match shell {
+            Shell::Bash => {
+                clap_complete::generate(clap_complete::Shell::Bash, &mut cmd, &name, buf)
+            }
+            Shell::Zsh => clap_complete::generate(clap_complete::Shell::Zsh, &mut cmd, &name, buf),
+            Shell::Fish => {
+                clap_complete::generate(clap_complete::Shell::Fish, &mut cmd, &name, buf)
+            }
+        }
+        return Ok(());
+    }
+
+    use clap_complete::env::EnvCompleter;
+    let result = match shell {
+        Shell::Bash => {
+            clap_complete::env::Bash.write_registration(COMPLETE_VAR, &name, &name, &name, buf)
+        }
+        Shell::Zsh => {
+            clap_complete::env::Zsh.write_registration(COMPLETE_VAR, &name, &name, &name, buf)
+        }
+        Shell::Fish => {
+            clap_complete::env::Fish.write_registration(COMPLETE_VAR, &name, &name, &name, buf)
+        }
+    };
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Complete resource names. Only a key held by the agent is used,
+/// completing never asks for the master password.
+fn complete_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    harden::apply();
+    let prefix = current.to_string_lossy();
+    let location = location_arg(std::env::args());
+    command::names(location.as_deref())
+        .into_iter()
+        .filter(|v| v.starts_with(prefix.as_ref()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// The `--location` typed so far, completers only get the current word.
+fn location_arg(args: impl Iterator<Item = String>) -> Option<String> {
+    let mut location = None;
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        if arg == "-l" || arg == "--location" {
+            location = args.peek().cloned();
+        } else if let Some(v) = arg.strip_prefix("--location=") {
+            location = Some(v.to_string());
+        }
+    }
+    location
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        from_matches(&Cli::command().try_get_matches_from(args)?)
+    }
+
+    #[test]
+    fn test_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_location_anywhere() {
+        for args in [
+            vec!["onepass", "get", "-l", "x.txt", "site"],
+            vec!["onepass", "-l", "x.txt", "get", "site"],
+            vec!["onepass", "get", "site", "--location=x.txt"],
+        ] {
+            let cli = parse(&args).expect("parsing");
+            assert_eq!(Some("x.txt".to_string()), cli.location);
+            match cli.command {
+                Command::Get { name, .. } => assert_eq!("site", name),
+                _ => panic!("expected get"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_flags() {
+        let cli = parse(&["onepass", "otp", "site", "--clear-after=5"]).expect("parsing");
+        match cli.command {
+            Command::Otp { clear_after, .. } => assert_eq!(Some(5), clear_after),
+            _ => panic!("expected otp"),
+        }
+
+        let cli = parse(&["onepass", "list", "-t", "work", "--url=example"]).expect("parsing");
+        match cli.command {
+            Command::List { tag, url } => {
+                assert_eq!(Some("work".to_string()), tag);
+                assert_eq!(Some("example".to_string()), url);
+            }
+            _ => panic!("expected list"),
+        }
+
+        let cli = parse(&["onepass", "find", "git"]).expect("parsing");
+        match cli.command {
+            Command::Find { query } => assert_eq!("git", query),
+            _ => panic!("expected find"),
+        }
+
+        let cli = parse(&["onepass", "doctor", "--no-harden"]).expect("parsing");
+        assert!(cli.no_harden);
+    }
+
+    #[test]
+    fn test_generator() {
+        let cli =
+            parse(&["onepass", "suggest", "--length", "20", "--symbols", "!#"]).expect("parsing");
+        let policy = match cli.command {
+            Command::Suggest { generator } => match generator.generator() {
+                password::Generator::Password(v) => v,
+                v => panic!("expected a password, got {:?}", v),
+            },
+            _ => panic!("expected suggest"),
+        };
+        assert_eq!(20, policy.length);
+        assert_eq!("!#", policy.symbols);
+
+        let expected = password::Policy {
+            symbols: String::new(),
+            exclude_ambiguous: true,
+            min_digits: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            password::Generator::Password(expected),
+            parse_generator("--no-symbols --exclude-ambiguous --min-digits 2")
+                .expect("parsing")
+                .generator()
+        );
+        let expected = password::Policy {
+            symbols: "!@".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            password::Generator::Password(expected),
+            parse_generator("--symbols '!@'")
+                .expect("parsing")
+                .generator()
+        );
+        assert_eq!(
+            password::Generator::default(),
+            parse_generator("").expect("parsing").generator()
+        );
+        assert!(parse_generator("--no-symbols --symbols !").is_err());
+        assert!(parse_generator("--symbols '!").is_err());
+    }
+
+    #[test]
+    fn test_generator_quoting() {
+        let symbols = |line: &str| match parse_generator(line).expect("parsing").generator() {
+            password::Generator::Password(v) => v.symbols,
+            v => panic!("expected a password, got {:?}", v),
+        };
+        assert_eq!("! @", symbols("--symbols '! @'"));
+        assert_eq!("\"", symbols("--symbols '\"'"));
+        assert_eq!("'", symbols("--symbols \"'\""));
+
+        match parse_generator("--passphrase --separator ' '")
+            .expect("parsing")
+            .generator()
+        {
+            password::Generator::Passphrase(v) => assert_eq!(" ", v.separator),
+            v => panic!("expected a passphrase, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_custom_generator() {
+        let custom = |args: &[&str]| match parse(args).expect("parsing").command {
+            Command::Rotate { generator, .. } => generator.custom(),
+            _ => panic!("expected rotate"),
+        };
+        assert_eq!(None, custom(&["onepass", "rotate", "site"]));
+        // Default values given explicitly replace a remembered policy.
+        assert_eq!(
+            Some(password::Generator::default()),
+            custom(&[
+                "onepass",
+                "rotate",
+                "site",
+                "--length",
+                "16",
+                "--min-digits",
+                "1"
+            ])
+        );
+        assert!(matches!(
+            custom(&["onepass", "rotate", "site", "--passphrase"]),
+            Some(password::Generator::Passphrase(_))
+        ));
+
+        assert_eq!(None, parse_generator("").expect("parsing").custom());
+        assert_eq!(
+            Some(password::Generator::default()),
+            parse_generator("--length 16").expect("parsing").custom()
+        );
+    }
+
+    #[test]
+    fn test_passphrase_options() {
+        let expected = password::Passphrase {
+            words: 4,
+            separator: ".".to_string(),
+            capitalize: true,
+            with_number: true,
+        };
+        assert_eq!(
+            password::Generator::Passphrase(expected),
+            parse_generator("--passphrase --words 4 --separator . --capitalize --with-number")
+                .expect("parsing")
+                .generator()
+        );
+        assert_eq!(
+            password::Generator::Passphrase(password::Passphrase::default()),
+            parse_generator("--passphrase")
+                .expect("parsing")
+                .generator()
+        );
+        assert!(parse_generator("--words 4").is_err());
+        assert!(parse_generator("--passphrase --length 20").is_err());
+    }
+
+    #[test]
+    fn test_completions() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            for dynamic in [false, true] {
+                let mut buf = vec![];
+                write_completions(shell, dynamic, &mut buf).expect("writing");
+                let script = String::from_utf8(buf).expect("utf8");
+                assert!(script.contains("onepass"));
+                if dynamic {
+                    assert!(script.contains(COMPLETE_VAR));
+                } else {
+                    assert!(script.contains("passwd"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_location_arg() {
+        let args = |v: &[&str]| {
+            v.iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        assert_eq!(None, location_arg(args(&["onepass", "get", ""])));
+        assert_eq!(
+            Some("x.txt".to_string()),
+            location_arg(args(&["onepass", "-l", "x.txt", "get", "si"]))
+        );
+        assert_eq!(
+            Some("x.txt".to_string()),
+            location_arg(args(&["onepass", "get", "--location=x.txt", "si"]))
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        assert!(parse(&["onepass"]).is_err());
+        assert!(parse(&["onepass", "get"]).is_err());
+        assert!(parse(&["onepass", "get", "site", "-c", "soon"]).is_err());
+        assert!(parse(&["onepass", "nope"]).is_err());
+        assert!(parse(&["onepass", "backup"]).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use arboard::Clipboard;
+
+use crate::secret::Secret;
+
+/// Hidden command the clipboard helper is started with.
+pub const HELPER_COMMAND: &str = "clipboard-helper";
+
+/// How often the helper checks whether the clipboard was replaced.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Put the value on the clipboard and clear it again after the timeout.
+///
+/// On X11 and Wayland the clipboard is served by the process that set it,
+/// so the value is handed over stdin to a detached helper which outlives
+/// this command. A zero timeout keeps the value until it is replaced.
+pub fn copy(value: &str, timeout: Duration) -> Result<(), String> {
+    let exe = match std::env::current_exe() {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let mut cmd = Command::new(exe);
+    cmd.arg(HELPER_COMMAND)
+        .arg(timeout.as_secs().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe.
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut child = match cmd.spawn() {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(err) = stdin.write_all(value.as_bytes()) {
+            return Err(err.to_string());
+        }
+    }
+
+    // The helper answers once it holds the clipboard, or with the reason it can't.
+    let mut reply = String::new();
+    if let Some(stdout) = child.stdout.take() {
+        if let Err(err) = BufReader::new(stdout).read_line(&mut reply) {
+            return Err(err.to_string());
+        }
+    }
+    match reply.trim() {
+        "ok" => Ok(()),
+        "" => Err("clipboard helper exited unexpectedly".to_string()),
+        v => Err(v.to_string()),
+    }
+}
+
+/// Run the clipboard helper: read the value from stdin, hold it on the
+/// clipboard and clear it after the timeout, unless it was replaced.
+pub fn serve(timeout: Duration) -> Result<(), String> {
+    let mut value = Secret::default();
+    if let Err(err) = std::io::stdin().read_to_string(value.expose_mut()) {
+        return Err(err.to_string());
+    }
+
+    let mut ctx = match Clipboard::new() {
+        Ok(v) => v,
+        Err(err) => return reply(&err.to_string()),
+    };
+    if let Err(err) = ctx.set_text(value.expose()) {
+        return reply(&err.to_string());
+    }
+    reply("ok")?;
+
+    let deadline = if timeout.is_zero() {
+        None
+    } else {
+        Some(Instant::now() + timeout)
+    };
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let current = ctx.get_text().ok().map(Secret::new);
+        let current = current.as_ref().map(|v| v.expose());
+        match step(current, value.expose(), Instant::now(), deadline) {
+            Step::Wait => continue,
+            Step::Clear => {
+                let _ = ctx.clear();
+                return Ok(());
+            }
+            Step::Stop => return Ok(()),
+        }
+    }
+}
+
+fn reply(msg: &str) -> Result<(), String> {
+    let mut stdout = std::io::stdout();
+    match writeln!(stdout, "{}", msg).and_then(|_| stdout.flush()) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    Wait,
+    Clear,
+    Stop,
+}
+
+/// Decide what the helper does next. Anything else on the clipboard
+/// was put there by the user, so the helper leaves it alone.
+fn step(current: Option<&str>, ours: &str, now: Instant, deadline: Option<Instant>) -> Step {
+    if current != Some(ours) {
+        return Step::Stop;
+    }
+    match deadline {
+        Some(v) if now >= v => Step::Clear,
+        _ => Step::Wait,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(30);
+
+        assert_eq!(Step::Wait, step(Some("secret"), "secret", now, Some(later)));
+        assert_eq!(
+            Step::Clear,
+            step(Some("secret"), "secret", later, Some(later))
+        );
+        assert_eq!(
+            Step::Stop,
+            step(Some("other"), "secret", later, Some(later))
+        );
+        assert_eq!(Step::Stop, step(None, "secret", now, Some(later)));
+        assert_eq!(Step::Wait, step(Some("secret"), "secret", later, None));
+    }
+}
@@ -1,215 +1,340 @@
-use std::io::Stdin;
+use std::fs::OpenOptions;
+use std::io::{Stdin, Write};
 use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::OpenOptionsExt;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
+use zeroize::Zeroizing;
+
+use crate::agent;
+use crate::audit;
+use crate::backup;
+use crate::breach;
+use crate::clipboard;
+use crate::config;
+use crate::export;
 use crate::file;
+use crate::harden;
+use crate::import;
 use crate::input;
+use crate::kdf;
+use crate::otp;
 use crate::password;
+use crate::picker;
 use crate::resource;
+use crate::secret::Secret;
+use crate::shell;
 use crate::text;
-
-use arboard::Clipboard;
+use crate::tui;
 
 pub static DONE: AtomicBool = AtomicBool::new(false);
 
-pub enum Kind {
-    New,
-    Get,
-    Del,
-    Help,
-    List,
-    Purge,
-    Update,
-    Suggest,
-}
-
-impl Kind {
-    pub fn from_string(s: &str) -> Option<Kind> {
-        match s {
-            "new" => Some(Kind::New),
-            "get" => Some(Kind::Get),
-            "del" => Some(Kind::Del),
-            "help" => Some(Kind::Help),
-            "list" => Some(Kind::List),
-            "purge" => Some(Kind::Purge),
-            "update" => Some(Kind::Update),
-            "suggest" => Some(Kind::Suggest),
-            _ => None,
+/// Get what unlocks the file: the key cached by the agent, or else the
+/// master password. While the agent runs, the key derived from the
+/// password is handed to it so the next commands do not have to ask.
+fn unlock(custom_path: Option<&str>) -> Result<kdf::Credential, String> {
+    if let Some(v) = agent::get(custom_path) {
+        if file::check(custom_path, &v).is_ok() {
+            return Ok(v);
         }
+        agent::forget(custom_path);
+    }
+
+    let password = input::master_password()?;
+    let is_empty = match std::fs::metadata(file::path(custom_path)) {
+        Ok(v) => v.size() == 0,
+        Err(_) => true,
+    };
+    if is_empty || !agent::running() {
+        return Ok(kdf::Credential::Password(password));
     }
+
+    let credential = file::unlock(custom_path, password.expose())?;
+    agent::put(custom_path, &credential);
+    Ok(credential)
 }
 
 /// Create a new resource and append it to the file.
-pub fn new(custom_path: Option<&str>, stdin: &mut Stdin) -> Result<(), String> {
+pub fn new(
+    custom_path: Option<&str>,
+    generator: &password::Generator,
+    stdin: &mut Stdin,
+) -> Result<(), String> {
     if !file::exists(custom_path) {
         if let Err(err) = file::create(custom_path) {
             return Err(err.to_string());
         }
     }
 
-    let resource = input::resource(stdin)?;
-    let password = input::master_password()?;
-    new_resource(custom_path, &password, resource)?;
+    let breach_db = config::load()?.breach_db;
+    let resource = input::resource(stdin, generator, breach_db.as_deref())?;
+    let credential = unlock(custom_path)?;
+    new_resource(custom_path, &credential, resource)?;
 
     DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
 
-fn new_resource(
+pub fn new_resource(
     custom_path: Option<&str>,
-    password: &str,
+    credential: &kdf::Credential,
     resource: resource::Instance,
 ) -> Result<(), String> {
+    let _lock = file::lock(custom_path)?;
     let path = file::path(custom_path);
     let metadata = match std::fs::metadata(path) {
         Ok(v) => v,
         Err(err) => return Err(err.to_string()),
     };
 
-    let mut content = String::new();
+    let mut content = Secret::default();
     if metadata.size() > 0 {
-        content = file::decrypt(custom_path, password)?;
-        let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-        for line in &lines {
-            if line.trim() == resource.name.trim() {
-                return Err("Resource already exists".to_string());
-            }
-        }
-        lines.push(resource.to_string());
-        content = lines.join("\n");
-    } else {
-        content.push_str(resource.to_string().as_str());
+        content = file::decrypt(custom_path, credential)?;
     }
+    let content = resource::add(resource, content.expose())?;
 
-    file::encrypt(custom_path, password, content)?;
+    file::encrypt(custom_path, credential, content)?;
     Ok(())
 }
 
-pub fn get(custom_path: Option<&str>, args: Vec<String>) -> Result<Clipboard, String> {
-    if args.len() < 3 {
-        return Err(text::MSG_COMMAND_GET.to_string());
-    }
+pub fn get(
+    custom_path: Option<&str>,
+    resource_name: &str,
+    clear_after: Option<u64>,
+    history: bool,
+) -> Result<(), String> {
+    let timeout = clipboard_timeout(clear_after)?;
 
     if !file::exists(custom_path) {
         return Err(text::MSG_NO_RESOURCES.to_string());
     }
 
-    let password = input::master_password()?;
-    let resource_name = &args[2];
-    if input::is_reserved(resource_name) {
-        return Err("use of reserved keyword".to_string());
+    let credential = unlock(custom_path)?;
+    let got = pick_resource(custom_path, &credential, resource_name)?;
+    if history {
+        if got.history.is_empty() {
+            println!("No older passwords");
+        }
+        for v in got.history.iter().rev() {
+            let replaced = backup::format_timestamp(u128::from(v.replaced) * 1000);
+            println!("Replaced {}: {}", replaced, v.password.expose());
+        }
     }
+    show_resource(&got, timeout);
 
-    let got = get_resource(custom_path, &password, resource_name)?;
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Print the details of a resource and copy its password.
+pub fn show_resource(got: &resource::Instance, timeout: Duration) {
     println!("Username: {}", got.user);
-    let mut ctx = match Clipboard::new() {
-        Ok(v) => v,
-        Err(err) => return Err(err.to_string()),
-    };
-    if ctx.set_text(got.password.to_owned()).is_err() {
-        println!("Password: {}", got.password);
-        println!("Don't forget to clear your terminal");
-    } else {
-        println!("Password copied to clipboard");
-    };
+    if !got.url.is_empty() {
+        println!("URL: {}", got.url);
+    }
+    if !got.tags.is_empty() {
+        println!("Tags: {}", got.tags.join(", "));
+    }
+    for (k, v) in &got.fields {
+        println!("{}: {}", k, v.expose());
+    }
+    if !got.otp.is_empty() {
+        println!("One-time codes: onepass otp {}", got.name);
+    }
+    if !got.notes.is_empty() {
+        println!("Notes:\n{}", got.notes);
+    }
+    copy("Password", got.password.expose(), timeout);
+}
+
+/// Put the value on the clipboard, or print it if the clipboard is not available.
+pub fn copy(label: &str, value: &str, timeout: Duration) {
+    match clipboard::copy(value, timeout) {
+        Ok(_) if timeout.is_zero() => println!("{} copied to clipboard", label),
+        Ok(_) => println!(
+            "{} copied to clipboard, clearing it in {}s",
+            label,
+            timeout.as_secs()
+        ),
+        Err(_) => {
+            println!("{}: {}", label, value);
+            println!("Don't forget to clear your terminal");
+        }
+    }
+}
+
+/// How long copied values stay on the clipboard: `--clear-after`
+/// or else `clipboard_timeout` from the config.
+pub fn clipboard_timeout(clear_after: Option<u64>) -> Result<Duration, String> {
+    match clear_after {
+        Some(v) => Ok(Duration::from_secs(v)),
+        None => Ok(Duration::from_secs(config::load()?.clipboard_timeout)),
+    }
+}
+
+/// Copy the current one-time code of a resource.
+pub fn otp(
+    custom_path: Option<&str>,
+    resource_name: &str,
+    clear_after: Option<u64>,
+) -> Result<(), String> {
+    let timeout = clipboard_timeout(clear_after)?;
+
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
+    }
+
+    let credential = unlock(custom_path)?;
+    let got = pick_resource(custom_path, &credential, resource_name)?;
+    let (code, remaining) = otp_code(&got)?;
+    println!("Valid for {}s", remaining);
+    copy("Code", &code, timeout);
 
     DONE.store(true, Ordering::Relaxed);
-    Ok(ctx)
+    Ok(())
 }
 
-fn get_resource(
+pub fn otp_code(got: &resource::Instance) -> Result<(String, u64), String> {
+    if got.otp.is_empty() {
+        return Err(format!(
+            "{} has no one-time password secret - add one with `onepass update {}`",
+            got.name, got.name
+        ));
+    }
+    let totp = otp::parse(got.otp.expose())?;
+    Ok(totp.now())
+}
+
+/// Get a resource by its exact name, or else let the user pick one of the
+/// closest matches. Without a terminal to pick on, the closest matches are
+/// only suggested.
+pub fn pick_resource(
     custom_path: Option<&str>,
-    password: &str,
+    credential: &kdf::Credential,
     resource_name: &str,
 ) -> Result<resource::Instance, String> {
-    let content = file::decrypt(custom_path, password)?;
-    let got = resource::get(resource_name, &content)?;
-    Ok(got)
+    let content = file::decrypt(custom_path, credential)?;
+    let err = match resource::get(resource_name, content.expose()) {
+        Ok(v) => return Ok(v),
+        Err(err) => err,
+    };
+    let resources = resource::list(content.expose(), &resource::Filter::default())?;
+    let rank = |query: &str| -> Vec<String> {
+        resource::rank(resources.clone(), query)
+            .into_iter()
+            .map(|v| v.name)
+            .collect()
+    };
+
+    if !picker::available() {
+        let closest = rank(resource_name);
+        if closest.is_empty() {
+            return Err(err);
+        }
+        let closest: Vec<&str> = closest.iter().take(3).map(|v| v.as_str()).collect();
+        return Err(format!("{}, did you mean: {}", err, closest.join(", ")));
+    }
+    match picker::pick(resource_name, rank)? {
+        Some(v) => resource::get(&v, content.expose()),
+        None => Err(err),
+    }
 }
 
-pub fn list(custom_path: Option<&str>) -> Result<(), String> {
+/// Resources ranked by how well their name, tags, URL or username match.
+pub fn find(custom_path: Option<&str>, query: &str) -> Result<(), String> {
     if !file::exists(custom_path) {
         return Err(text::MSG_NO_RESOURCES.to_string());
     }
 
-    let password = input::master_password()?;
-
-    let result = list_resources(custom_path, &password)?;
+    let credential = unlock(custom_path)?;
+    let content = file::decrypt(custom_path, &credential)?;
+    let result = resource::find(content.expose(), query)?;
     if result.is_empty() {
+        return Err(text::MSG_NO_MATCHES.to_string());
+    }
+    print_resources(&result);
+    DONE.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+pub fn list(custom_path: Option<&str>, filter: resource::Filter) -> Result<(), String> {
+    if !file::exists(custom_path) {
         return Err(text::MSG_NO_RESOURCES.to_string());
     }
-    for v in result {
-        println!("{}", v);
+
+    let credential = unlock(custom_path)?;
+
+    let result = list_resources(custom_path, &credential, &filter)?;
+    if result.is_empty() {
+        return Err(text::MSG_NO_RESOURCES.to_string());
     }
+    print_resources(&result);
     DONE.store(true, Ordering::Relaxed);
 
     Ok(())
 }
 
-fn list_resources(custom_path: Option<&str>, password: &str) -> Result<Vec<String>, String> {
-    let content = file::decrypt(custom_path, password)?;
-
-    let mut result: Vec<String> = vec![];
-    let lines: Vec<&str> = content.lines().collect();
-    for (i, v) in lines.iter().enumerate() {
-        if *v == text::RESERVED_RESOURCE {
-            result.push(lines[i + 1].to_string());
+pub fn print_resources(resources: &[resource::Instance]) {
+    for v in resources {
+        if v.tags.is_empty() {
+            println!("{}", v.name);
+        } else {
+            println!("{} [{}]", v.name, v.tags.join(", "));
         }
     }
+}
 
-    Ok(result)
+pub fn list_resources(
+    custom_path: Option<&str>,
+    credential: &kdf::Credential,
+    filter: &resource::Filter,
+) -> Result<Vec<resource::Instance>, String> {
+    let content = file::decrypt(custom_path, credential)?;
+    resource::list(content.expose(), filter)
 }
 
-pub fn purge() -> Result<(), String> {
-    if let Err(err) = file::purge(None) {
+pub fn purge(custom_path: Option<&str>) -> Result<(), String> {
+    if let Err(err) = file::purge(custom_path) {
         return Err(err.to_string());
     };
     DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
 
-pub fn suggest() -> String {
+/// Print a new password, with its entropy on stderr to keep it out of pipes.
+pub fn suggest(generator: &password::Generator) -> Result<(), String> {
+    let password = generator.generate()?;
+    println!("{}", password);
+    eprintln!("About {:.0} bits of entropy", generator.entropy());
     DONE.store(true, Ordering::Relaxed);
-    password::suggest(16)
+    Ok(())
 }
 
-pub fn update(
-    custom_path: Option<&str>,
-    args: Vec<String>,
-    stdin: &mut Stdin,
-) -> Result<(), String> {
+pub fn update(custom_path: Option<&str>, name: &str, stdin: &mut Stdin) -> Result<(), String> {
     if !file::exists(custom_path) {
         return Err(text::MSG_NO_RESOURCES.to_string());
     }
 
-    if args.len() < 3 {
-        return Err(text::MSG_COMMAND_UPDATE.to_string());
-    }
-
-    let name = args[2].clone();
-    if input::is_reserved(&name) {
-        return Err("use of reserved keyword".to_string());
-    }
-    let password = input::master_password()?;
+    let credential = unlock(custom_path)?;
     let (key, val) = input::update_resource(stdin)?;
 
-    update_resource(custom_path, &password, name, key, val)?;
+    update_resource(custom_path, &credential, name.to_string(), key, val)?;
 
     DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
 
-fn update_resource(
+pub fn update_resource(
     custom_path: Option<&str>,
-    password: &str,
+    credential: &kdf::Credential,
     name: String,
     key: resource::Key,
-    val: String,
+    val: Secret,
 ) -> Result<(), String> {
-    let content = file::decrypt(custom_path, password)?;
-
-    resource::get(&name, &content)?;
-
+    let _lock = file::lock(custom_path)?;
+    let content = file::decrypt(custom_path, credential)?;
     let updated = resource::update(resource::UpdateInput {
         key,
         val,
@@ -217,54 +342,571 @@ fn update_resource(
         content,
     })?;
 
-    file::encrypt(custom_path, password, updated)?;
+    file::encrypt(custom_path, credential, updated)?;
 
     Ok(())
 }
 
-pub fn del(custom_path: Option<&str>, args: Vec<String>) -> Result<(), String> {
-    if args.len() < 3 {
-        return Err(text::MSG_COMMAND_DEL.to_string());
+/// Generate a new password for a resource with its remembered generator,
+/// or `custom` which is then remembered instead, and store it once confirmed.
+pub fn rotate(
+    custom_path: Option<&str>,
+    name: &str,
+    custom: Option<password::Generator>,
+    clear_after: Option<u64>,
+    stdin: &mut Stdin,
+) -> Result<(), String> {
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
     }
 
+    let timeout = clipboard_timeout(clear_after)?;
+    let credential = unlock(custom_path)?;
+    let got = pick_resource(custom_path, &credential, name)?;
+    rotate_resource(custom_path, &credential, got, custom, timeout, stdin)?;
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn rotate_resource(
+    custom_path: Option<&str>,
+    credential: &kdf::Credential,
+    got: resource::Instance,
+    custom: Option<password::Generator>,
+    timeout: Duration,
+    stdin: &mut Stdin,
+) -> Result<(), String> {
+    let generator = custom.or(got.generator).unwrap_or_default();
+    let password = Secret::new(generator.generate()?);
+    println!("New password for {}: {}", got.name, password.expose());
+    println!("About {:.0} bits of entropy", generator.entropy());
+    if !input::confirm(stdin, "replace the current password with it?")? {
+        println!("Kept the current password");
+        return Ok(());
+    }
+
+    let _lock = file::lock(custom_path)?;
+    let content = file::decrypt(custom_path, credential)?;
+    let updated = resource::rotate(&got.name, password.clone(), generator, content.expose())?;
+    file::encrypt(custom_path, credential, updated)?;
+    println!("Replaced, the old password is kept in the history");
+    copy("Password", password.expose(), timeout);
+    Ok(())
+}
+
+pub fn del(custom_path: Option<&str>, name: &str) -> Result<(), String> {
     if !file::exists(custom_path) {
         return Err(text::MSG_NO_RESOURCES.to_string());
     }
 
-    let password = input::master_password()?;
-    let name = &args[2];
-    if input::is_reserved(name) {
-        return Err("Keyword is reserved".to_string());
+    let credential = unlock(custom_path)?;
+    delete_resource(custom_path, &credential, name)?;
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn delete_resource(
+    custom_path: Option<&str>,
+    credential: &kdf::Credential,
+    name: &str,
+) -> Result<(), String> {
+    let _lock = file::lock(custom_path)?;
+    let content = file::decrypt(custom_path, credential)?;
+    let deleted = resource::delete(name, content.expose())?;
+    file::encrypt(custom_path, credential, deleted)?;
+    Ok(())
+}
+
+/// Import the resources of another password manager's export.
+pub fn import(
+    custom_path: Option<&str>,
+    format: import::Format,
+    export: &str,
+    duplicates: import::Duplicates,
+    dry_run: bool,
+) -> Result<(), String> {
+    let bytes = match std::fs::read(export) {
+        Ok(v) => Zeroizing::new(v),
+        Err(err) => return Err(format!("{}: {}", export, err)),
+    };
+    let data = if format == import::Format::Onepass && file::is_sealed(&bytes) {
+        let passphrase = input::archive_passphrase(false)?;
+        file::unseal(&bytes, passphrase.expose())?
+    } else {
+        match std::str::from_utf8(&bytes) {
+            Ok(v) => Secret::from(v),
+            Err(_) => return Err(format!("{} is not a text file", export)),
+        }
+    };
+    let imported = import::parse(format, data.expose())?;
+    if imported.is_empty() {
+        return Err(format!("{} holds no resources", export));
     }
 
-    delete_resource(custom_path, &password, name)?;
+    let report = if dry_run && !file::exists(custom_path) {
+        import::merge(vec![], imported, duplicates).1
+    } else {
+        if !file::exists(custom_path) {
+            if let Err(err) = file::create(custom_path) {
+                return Err(err.to_string());
+            }
+        }
+        let credential = unlock(custom_path)?;
+        import_resources(custom_path, &credential, imported, duplicates, dry_run)?
+    };
+
+    let (added, renamed, replaced, skipped) = if dry_run {
+        ("Would add", "Would rename", "Would replace", "Would skip")
+    } else {
+        ("Added", "Renamed", "Replaced", "Skipped")
+    };
+    for v in &report.added {
+        println!("{} {}", added, v);
+    }
+    for (from, to) in &report.renamed {
+        println!("{} {} to {}", renamed, from, to);
+    }
+    for v in &report.replaced {
+        println!("{} {}", replaced, v);
+    }
+    for v in &report.skipped {
+        println!("{} {}, the name is taken", skipped, v);
+    }
+    println!(
+        "{} new, {} renamed, {} replaced, {} skipped",
+        report.added.len(),
+        report.renamed.len(),
+        report.replaced.len(),
+        report.skipped.len()
+    );
+    if !dry_run && !file::is_sealed(&bytes) {
+        println!(
+            "Don't forget to delete {}, it holds your passwords in plain text",
+            export
+        );
+    }
 
     DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
 
-fn delete_resource(custom_path: Option<&str>, password: &str, name: &str) -> Result<(), String> {
-    let content = file::decrypt(custom_path, password)?;
-    let deleted = resource::delete(name, content)?;
-    file::encrypt(custom_path, password, deleted)?;
-    Ok(())
+fn import_resources(
+    custom_path: Option<&str>,
+    credential: &kdf::Credential,
+    imported: Vec<resource::Instance>,
+    duplicates: import::Duplicates,
+    dry_run: bool,
+) -> Result<import::Report, String> {
+    let _lock = file::lock(custom_path)?;
+    let path = file::path(custom_path);
+    let metadata = match std::fs::metadata(path) {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let mut content = Secret::default();
+    if metadata.size() > 0 {
+        content = file::decrypt(custom_path, credential)?;
+    }
+    let resources = resource::parse(content.expose())?;
+    let (resources, report) = import::merge(resources, imported, duplicates);
+    if !dry_run {
+        file::encrypt(custom_path, credential, resource::serialize(&resources)?)?;
+    }
+    Ok(report)
 }
 
-pub fn help(args: Vec<String>) -> String {
-    if args.len() != 3 {
-        return text::MSG_HELP.to_string();
+/// Write all resources to a file, or to stdout if there is none.
+/// Plain text formats need `plaintext` to be set as a confirmation.
+pub fn export(
+    custom_path: Option<&str>,
+    format: export::Format,
+    output: Option<&str>,
+    plaintext: bool,
+) -> Result<(), String> {
+    if format.is_plaintext() && !plaintext {
+        return Err(
+            "The export would hold your passwords in plain text - confirm with --plaintext, \
+             or export an encrypted archive with --format archive"
+                .to_string(),
+        );
+    }
+    if format == export::Format::Archive && output.is_none() {
+        return Err("Choose where to write the archive with --output".to_string());
+    }
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
+    }
+
+    let credential = unlock(custom_path)?;
+    let content = file::decrypt(custom_path, &credential)?;
+    let resources = resource::parse(content.expose())?;
+    let bytes = match format {
+        export::Format::Json => Zeroizing::new(export::json(&resources)?.expose().into()),
+        export::Format::Csv => Zeroizing::new(export::csv(&resources)?.expose().into()),
+        export::Format::Archive => {
+            let passphrase = input::archive_passphrase(true)?;
+            let content = export::json(&resources)?;
+            Zeroizing::new(file::seal(passphrase.expose(), &content)?)
+        }
     };
 
-    if let Some(command) = Kind::from_string(&args[2]) {
-        match command {
-            Kind::Get => text::MSG_COMMAND_GET.to_string(),
-            Kind::Del => text::MSG_COMMAND_DEL.to_string(),
-            Kind::Update => text::MSG_COMMAND_UPDATE.to_string(),
-            _ => text::MSG_HELP.to_string(),
+    match output {
+        Some(path) => {
+            write_new(path, &bytes)?;
+            println!("Exported {} resources to {}", resources.len(), path);
+        }
+        None => {
+            if let Err(err) = std::io::stdout().write_all(&bytes) {
+                return Err(err.to_string());
+            }
+        }
+    }
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn audit(
+    custom_path: Option<&str>,
+    json: bool,
+    fail_on: Option<audit::Severity>,
+    max_age_days: u64,
+) -> Result<(), String> {
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
+    }
+
+    let credential = unlock(custom_path)?;
+    let content = file::decrypt(custom_path, &credential)?;
+    let resources = resource::parse(content.expose())?;
+    let findings = audit::audit(&resources, resource::now(), max_age_days);
+    let count = |severity| findings.iter().filter(|v| v.severity == severity).count();
+
+    if json {
+        let report = serde_json::json!({
+            "checked": resources.len(),
+            "findings": findings,
+        });
+        match serde_json::to_string_pretty(&report) {
+            Ok(v) => println!("{}", v),
+            Err(err) => return Err(err.to_string()),
         }
     } else {
-        text::MSG_HELP.to_string()
+        for v in &findings {
+            println!(
+                "{:<7} {:<9} {}: {}",
+                v.severity.to_string(),
+                v.kind.to_string(),
+                v.resources.join(", "),
+                v.message
+            );
+        }
+        println!(
+            "Checked {} resources: {} high, {} medium, {} low",
+            resources.len(),
+            count(audit::Severity::High),
+            count(audit::Severity::Medium),
+            count(audit::Severity::Low)
+        );
     }
+    DONE.store(true, Ordering::Relaxed);
+
+    if let Some(threshold) = fail_on {
+        let failed = findings.iter().filter(|v| v.severity >= threshold).count();
+        if failed > 0 {
+            return Err(format!(
+                "{} findings of severity {} or higher",
+                failed, threshold
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check every password against a local Have I Been Pwned copy, `db` or
+/// the one from the config. Fails if any was found.
+pub fn breach_check(custom_path: Option<&str>, db: Option<&str>) -> Result<(), String> {
+    let path = match db {
+        Some(v) => v.to_string(),
+        None => match config::load()?.breach_db {
+            Some(v) => v,
+            None => {
+                return Err(
+                    "Choose the breach data with --db or `breach_db` in the config".to_string(),
+                )
+            }
+        },
+    };
+    let db = breach::Db::open(&path)?;
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
+    }
+
+    let credential = unlock(custom_path)?;
+    let content = file::decrypt(custom_path, &credential)?;
+    let resources = resource::parse(content.expose())?;
+    let mut found = 0;
+    for v in resources.iter().filter(|v| !v.password.is_empty()) {
+        let count = db.count(v.password.expose())?;
+        if count > 0 {
+            println!("{}: password appears {} times in breaches", v.name, count);
+            found += 1;
+        }
+    }
+    DONE.store(true, Ordering::Relaxed);
+
+    match found {
+        0 => {
+            println!("None of {} passwords found in breaches", resources.len());
+            Ok(())
+        }
+        _ => Err(format!("{} compromised passwords, change them", found)),
+    }
+}
+
+/// Write a file only the user can read, refusing to overwrite one.
+fn write_new(path: &str, bytes: &[u8]) -> Result<(), String> {
+    let mut f = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+    {
+        Ok(v) => v,
+        Err(err) => return Err(format!("{}: {}", path, err)),
+    };
+    match f.write_all(bytes).and_then(|_| f.sync_all()) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("{}: {}", path, err)),
+    }
+}
+
+/// Browse and change the file in a full-screen interface until quit.
+pub fn tui(custom_path: Option<&str>, lock_after: Option<u64>) -> Result<(), String> {
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
+    }
+
+    let config = config::load()?;
+    let credential = match unlock(custom_path)? {
+        // Derive the key once instead of on every change.
+        kdf::Credential::Password(v) => file::unlock(custom_path, v.expose())?,
+        v => v,
+    };
+    let settings = tui::Settings {
+        lock_after: Duration::from_secs(lock_after.unwrap_or(config.lock_after)),
+        clipboard_timeout: Duration::from_secs(config.clipboard_timeout),
+    };
+    tui::run(custom_path, credential, settings)?;
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Run commands at a prompt, unlocking the file only once.
+pub fn shell(
+    custom_path: Option<&str>,
+    lock_after: Option<u64>,
+    stdin: &mut Stdin,
+) -> Result<(), String> {
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
+    }
+
+    let config = config::load()?;
+    let credential = match unlock(custom_path)? {
+        kdf::Credential::Password(v) => file::unlock(custom_path, v.expose())?,
+        v => v,
+    };
+    let settings = tui::Settings {
+        lock_after: Duration::from_secs(lock_after.unwrap_or(config.lock_after)),
+        clipboard_timeout: Duration::from_secs(config.clipboard_timeout),
+    };
+    shell::run(custom_path, credential, settings, stdin)?;
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// List the backups of the file.
+pub fn backup_list(custom_path: Option<&str>) -> Result<(), String> {
+    let list = match backup::list(custom_path) {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+    if list.is_empty() {
+        return Err(text::MSG_NO_BACKUPS.to_string());
+    }
+    for entry in list {
+        println!("{}", entry);
+    }
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Replace the file with one of its backups.
+pub fn backup_restore(custom_path: Option<&str>, id: &str) -> Result<(), String> {
+    let credential = kdf::Credential::Password(input::master_password()?);
+    restore_backup(custom_path, &credential, id)?;
+    println!("Restored backup {}", id);
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+fn restore_backup(
+    custom_path: Option<&str>,
+    credential: &kdf::Credential,
+    id: &str,
+) -> Result<(), String> {
+    let _lock = file::lock(custom_path)?;
+    let entry = backup::get(custom_path, id)?;
+    let backup_path = entry.path.to_string_lossy().to_string();
+    file::check(Some(&backup_path), credential)?;
+
+    let retention = config::load()?.backups;
+    if let Err(err) = backup::restore(custom_path, &entry, retention) {
+        return Err(err.to_string());
+    }
+    Ok(())
+}
+
+/// Change the master password and re-encrypt the file with it.
+pub fn passwd(custom_path: Option<&str>) -> Result<(), String> {
+    if !file::exists(custom_path) {
+        return Err(text::MSG_NO_RESOURCES.to_string());
+    }
+
+    // Never the agent's key: changing it takes knowing the current one.
+    let password = input::master_password()?;
+    let credential = file::unlock(custom_path, password.expose())?;
+    let new_password = input::new_master_password()?;
+    let left = change_password(custom_path, &credential, new_password.expose())?;
+    agent::forget(custom_path);
+    println!("Master password changed, backups are encrypted with it too");
+    if left > 0 {
+        println!(
+            "{} older backups in {} still open with an earlier master password only, delete them if it may be known",
+            left,
+            backup::dir(custom_path).display()
+        );
+    }
+
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns how many backups could not be re-encrypted, see `file::rekey_backups`.
+fn change_password(
+    custom_path: Option<&str>,
+    credential: &kdf::Credential,
+    new_password: &str,
+) -> Result<usize, String> {
+    let _lock = file::lock(custom_path)?;
+    let content = file::decrypt(custom_path, credential)?;
+    file::rekey(custom_path, new_password, content)?;
+    // Derive the new key once instead of for every backup.
+    let new = file::unlock(custom_path, new_password)?;
+    file::rekey_backups(custom_path, credential, &new)
+}
+
+/// Names of all resources, for shell completion. Empty unless the agent
+/// holds the key, so completing never prompts.
+pub fn names(custom_path: Option<&str>) -> Vec<String> {
+    let credential = match agent::get(custom_path) {
+        Some(v) => v,
+        None => return vec![],
+    };
+    match list_resources(custom_path, &credential, &resource::Filter::default()) {
+        Ok(v) => v.into_iter().map(|v| v.name).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Start the agent that keeps unlocked files unlocked.
+pub fn agent(timeout: Option<u64>, foreground: bool) -> Result<(), String> {
+    let timeout = match timeout {
+        Some(v) => Duration::from_secs(v),
+        None => Duration::from_secs(config::load()?.agent_timeout),
+    };
+
+    if foreground {
+        // Nothing to clean up on ctrl-c, the socket is replaced on the next start.
+        DONE.store(true, Ordering::Relaxed);
+        return agent::serve(timeout);
+    }
+    agent::start(timeout)?;
+    println!(
+        "Agent started, unlocked files stay unlocked for {}s when idle",
+        timeout.as_secs()
+    );
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn agent_stop() -> Result<(), String> {
+    agent::stop()?;
+    println!("Agent stopped");
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Make the agent forget every key right away.
+pub fn lock() -> Result<(), String> {
+    agent::lock()?;
+    println!("Locked");
+    DONE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Show which protections are active, for checking a setup.
+pub fn doctor(custom_path: Option<&str>) {
+    let fn_yes_no = |v: bool| if v { "yes" } else { "no" };
+
+    println!(
+        "Hardening: {}",
+        match harden::enabled() {
+            true => "on",
+            false => "off (--no-harden)",
+        }
+    );
+    match harden::dumpable() {
+        Some(v) => println!(
+            "Core dumps and ptrace blocked (PR_SET_DUMPABLE=0): {}",
+            fn_yes_no(!v)
+        ),
+        None => println!("Core dumps and ptrace blocked (PR_SET_DUMPABLE=0): not available"),
+    }
+    println!(
+        "Core file size limit is 0: {}",
+        fn_yes_no(harden::core_limit() == Some(0))
+    );
+    println!(
+        "Keys locked out of swap (mlock): {}",
+        fn_yes_no(kdf::Key::zeroed().is_locked())
+    );
+    match harden::ptrace_scope() {
+        Some(v) => println!("Yama ptrace scope: {}", v),
+        None => println!("Yama ptrace scope: not available"),
+    }
+    if let Ok(metadata) = std::fs::metadata(file::path(custom_path)) {
+        println!(
+            "File only accessible by you: {}",
+            fn_yes_no(metadata.mode() & 0o077 == 0)
+        );
+    }
+    println!("Agent running: {}", fn_yes_no(agent::running()));
+
+    DONE.store(true, Ordering::Relaxed);
+}
+
+/// Run the helper started by `copy`, see `clipboard::serve`.
+pub fn clipboard_helper(timeout: u64) -> Result<(), String> {
+    clipboard::serve(Duration::from_secs(timeout))
 }
 
 #[cfg(test)]
@@ -289,37 +931,35 @@ mod tests {
     impl Drop for Cleanup {
         fn drop(&mut self) {
             let file_path = format!("{}/{}.txt", file::DEFAULT_DIR_NAME, self.file_name);
+            for entry in backup::list(Some(&file_path)).expect("listing backups") {
+                std::fs::remove_file(entry.path).expect("cleaning up backup");
+            }
             file::purge(Some(&file_path)).expect("cleaning up");
         }
     }
 
-    fn seed(path: &str, amount: u8) -> String {
-        let password = password::suggest(16);
+    fn seed(path: &str, amount: u8) -> kdf::Credential {
+        let password = kdf::Credential::Password(Secret::new(
+            password::generate(&Default::default()).expect("generating"),
+        ));
 
         for i in 0..amount {
             if let Err(err) = new_resource(
                 Some(path),
                 &password,
                 resource::Instance {
-                    name: String::from(format!("name{}", i)),
-                    user: String::from(format!("user{}", i)),
-                    password: String::from(format!("password{}", i)),
+                    name: format!("name{}", i),
+                    user: format!("user{}", i),
+                    password: Secret::new(format!("password{}", i)),
+                    tags: vec![format!("tag{}", i % 2)],
+                    ..Default::default()
                 },
             ) {
                 panic!("seeding: {}", err)
             }
         }
 
-        password.to_string()
-    }
-
-    fn count_lines(path: &str, password: &str) -> Result<usize, String> {
-        let mut count: usize = 0;
-        let content = file::decrypt(Some(path), &password)?;
-        for _ in content.lines().into_iter() {
-            count += 1;
-        }
-        Ok(count)
+        password
     }
 
     #[test]
@@ -336,11 +976,11 @@ mod tests {
         let resource_password = "password3";
         let master_password = seed(t_path, 5);
         let got =
-            get_resource(Some(&t_path), &master_password, resource_name).expect("getting resource");
+            pick_resource(Some(t_path), &master_password, resource_name).expect("getting resource");
 
         assert_eq!(resource_name, got.name);
         assert_eq!(resource_user, got.user);
-        assert_eq!(resource_password, got.password);
+        assert_eq!(resource_password, got.password.expose());
     }
 
     #[test]
@@ -353,8 +993,18 @@ mod tests {
         file::create(Some(t_path)).expect("creating");
 
         let master_password = seed(t_path, 5);
-        let list = list_resources(Some(t_path), &master_password).expect("listing");
+        let list = list_resources(Some(t_path), &master_password, &resource::Filter::default())
+            .expect("listing");
         assert_eq!(5, list.len());
+
+        let filter = resource::Filter {
+            tag: Some("tag1".to_string()),
+            ..Default::default()
+        };
+        let list = list_resources(Some(t_path), &master_password, &filter).expect("listing");
+        assert_eq!(2, list.len());
+        assert_eq!("name1", list[0].name);
+        assert_eq!("name3", list[1].name);
     }
 
     #[test]
@@ -376,63 +1026,54 @@ mod tests {
             &master_password,
             "name0".to_string(),
             resource::Key::Name,
-            new_name.to_string(),
+            Secret::from(new_name),
         )
         .expect("updating name");
 
-        let list = list_resources(Some(t_path), &master_password).expect("listing");
+        let list = list_resources(Some(t_path), &master_password, &resource::Filter::default())
+            .expect("listing");
         assert_eq!(5, list.len());
-        assert_eq!(
-            5 * 4,
-            count_lines(t_path, &master_password).expect("counting lintes")
-        );
 
-        let got = get_resource(Some(t_path), &master_password, new_name).expect("getting name");
+        let got = pick_resource(Some(t_path), &master_password, new_name).expect("getting name");
         assert_eq!(new_name, got.name);
         assert_eq!("user0", got.user);
-        assert_eq!("password0", got.password);
+        assert_eq!("password0", got.password.expose());
 
         update_resource(
             Some(t_path),
             &master_password,
             "name1".to_string(),
             resource::Key::User,
-            new_user.to_string(),
+            Secret::from(new_user),
         )
         .expect("updating user");
 
-        let list = list_resources(Some(t_path), &master_password).expect("listing");
+        let list = list_resources(Some(t_path), &master_password, &resource::Filter::default())
+            .expect("listing");
         assert_eq!(5, list.len());
-        assert_eq!(
-            5 * 4,
-            count_lines(t_path, &master_password).expect("counting lintes")
-        );
 
-        let got = get_resource(Some(t_path), &master_password, "name1").expect("getting name");
+        let got = pick_resource(Some(t_path), &master_password, "name1").expect("getting name");
         assert_eq!("name1", got.name);
         assert_eq!(new_user, got.user);
-        assert_eq!("password1", got.password);
+        assert_eq!("password1", got.password.expose());
 
         update_resource(
             Some(t_path),
             &master_password,
             "name2".to_string(),
             resource::Key::Password,
-            new_password.to_string(),
+            Secret::from(new_password),
         )
         .expect("updating password");
 
-        let list = list_resources(Some(t_path), &master_password).expect("listing");
+        let list = list_resources(Some(t_path), &master_password, &resource::Filter::default())
+            .expect("listing");
         assert_eq!(5, list.len());
-        assert_eq!(
-            5 * 4,
-            count_lines(t_path, &master_password).expect("counting lintes")
-        );
 
-        let got = get_resource(Some(t_path), &master_password, "name2").expect("getting password");
+        let got = pick_resource(Some(t_path), &master_password, "name2").expect("getting password");
         assert_eq!("name2", got.name);
         assert_eq!("user2", got.user);
-        assert_eq!(new_password, got.password);
+        assert_eq!(new_password, got.password.expose());
     }
 
     #[test]
@@ -448,7 +1089,156 @@ mod tests {
 
         delete_resource(Some(t_path), &master_password, "name1").expect("deleting");
 
-        let list = list_resources(Some(t_path), &master_password).expect("listing");
+        let list = list_resources(Some(t_path), &master_password, &resource::Filter::default())
+            .expect("listing");
         assert_eq!(4, list.len());
     }
+
+    #[test]
+    fn test_import_resources() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        file::create(Some(t_path)).expect("creating");
+        let master_password = seed(t_path, 2);
+
+        let data = "name,url,username,password,note\nname1,,me,pw,\nmail,,me,pw,\n";
+        let imported = || import::parse(import::Format::Chrome, data).expect("parsing");
+
+        let report = import_resources(
+            Some(t_path),
+            &master_password,
+            imported(),
+            import::Duplicates::Rename,
+            true,
+        )
+        .expect("dry run");
+        assert_eq!(vec!["mail"], report.added);
+        let list = list_resources(Some(t_path), &master_password, &resource::Filter::default())
+            .expect("listing");
+        assert_eq!(2, list.len());
+
+        import_resources(
+            Some(t_path),
+            &master_password,
+            imported(),
+            import::Duplicates::Rename,
+            false,
+        )
+        .expect("importing");
+        let got = pick_resource(Some(t_path), &master_password, "name1 (2)").expect("getting");
+        assert_eq!("pw", got.password.expose());
+        let list = list_resources(Some(t_path), &master_password, &resource::Filter::default())
+            .expect("listing");
+        assert_eq!(4, list.len());
+    }
+
+    #[test]
+    fn test_restore_backup() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        file::create(Some(t_path)).expect("creating");
+
+        let master_password = seed(t_path, 3);
+        delete_resource(Some(t_path), &master_password, "name1").expect("deleting");
+        assert_eq!(
+            2,
+            list_resources(Some(t_path), &master_password, &resource::Filter::default())
+                .expect("listing")
+                .len()
+        );
+
+        let backups = backup::list(Some(t_path)).expect("listing backups");
+        let before_delete = backups.last().expect("last backup").id.to_string();
+
+        assert_eq!(
+            "Incorrect password - aborting.",
+            restore_backup(
+                Some(t_path),
+                &kdf::Credential::Password(Secret::from("wrong")),
+                &before_delete
+            )
+            .unwrap_err()
+        );
+        restore_backup(Some(t_path), &master_password, &before_delete).expect("restoring");
+
+        let got = pick_resource(Some(t_path), &master_password, "name1").expect("getting");
+        assert_eq!("user1", got.user);
+        assert_eq!(
+            3,
+            list_resources(Some(t_path), &master_password, &resource::Filter::default())
+                .expect("listing")
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_change_password() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        file::create(Some(t_path)).expect("creating");
+
+        let master_password = seed(t_path, 3);
+        let new_password = "new_master_password";
+        let wrong = kdf::Credential::Password(Secret::from("wrong"));
+        assert!(change_password(Some(t_path), &wrong, new_password).is_err());
+        change_password(Some(t_path), &master_password, new_password).expect("changing");
+
+        let filter = resource::Filter::default();
+        assert!(list_resources(Some(t_path), &master_password, &filter).is_err());
+        let new_credential = kdf::Credential::Password(Secret::from(new_password));
+        let list = list_resources(Some(t_path), &new_credential, &filter).expect("listing");
+        assert_eq!(3, list.len());
+
+        // The backups, the one of the old password included, moved along.
+        let backups = backup::list(Some(t_path)).expect("listing backups");
+        assert!(!backups.is_empty());
+        for entry in backups {
+            let path = entry.path.to_string_lossy().to_string();
+            assert!(file::check(Some(&path), &master_password).is_err());
+            file::check(Some(&path), &new_credential).expect("checking backup");
+        }
+    }
+
+    #[test]
+    fn test_clipboard_timeout() {
+        assert_eq!(Ok(Duration::from_secs(5)), clipboard_timeout(Some(5)));
+        assert_eq!(Ok(Duration::ZERO), clipboard_timeout(Some(0)));
+    }
+
+    #[test]
+    fn test_otp_code() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        file::create(Some(t_path)).expect("creating");
+        let master_password = seed(t_path, 2);
+
+        let got = pick_resource(Some(t_path), &master_password, "name0").expect("getting");
+        let err = otp_code(&got).unwrap_err();
+        assert!(err.contains("no one-time password secret"));
+
+        update_resource(
+            Some(t_path),
+            &master_password,
+            "name0".to_string(),
+            resource::Key::Otp,
+            Secret::from("otpauth://totp/x?secret=GEZDGNBVGY3TQOJQ&digits=8&period=60"),
+        )
+        .expect("updating otp");
+        let got = pick_resource(Some(t_path), &master_password, "name0").expect("getting");
+        let (code, remaining) = otp_code(&got).expect("otp");
+        assert_eq!(8, code.len());
+        assert!(remaining > 0 && remaining <= 60);
+    }
 }
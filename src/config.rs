@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use crate::file;
+
+pub const FILE_NAME: &str = "config";
+
+/// User settings, read from `~/.onepass/config`.
+///
+/// The file holds one `key = value` pair per line, `#` starts a comment:
+///
+/// ```text
+/// # keep the last 20 versions of the vault
+/// backups = 20
+/// # forget the unlocked vault after 5 idle minutes
+/// agent_timeout = 300
+/// # clear copied passwords after 10 seconds
+/// clipboard_timeout = 10
+/// # lock `onepass tui` after a minute without input
+/// lock_after = 60
+/// # warn about typed passwords found in this Have I Been Pwned copy
+/// breach_db = /srv/hibp/pwned-passwords-sha1-ordered-by-hash.txt
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Number of backups kept per vault, 0 disables backups.
+    pub backups: usize,
+    /// Seconds the agent keeps an unused key.
+    pub agent_timeout: u64,
+    /// Seconds a copied value stays on the clipboard, 0 keeps it until replaced.
+    pub clipboard_timeout: u64,
+    /// Seconds an interactive session stays unlocked without input, 0 never locks.
+    pub lock_after: u64,
+    /// Local Have I Been Pwned hashes to check typed passwords against.
+    pub breach_db: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backups: 10,
+            agent_timeout: 15 * 60,
+            clipboard_timeout: 30,
+            lock_after: 5 * 60,
+            breach_db: None,
+        }
+    }
+}
+
+pub fn path() -> PathBuf {
+    let mut path = file::path(Some(file::DEFAULT_DIR_NAME));
+    path.push(FILE_NAME);
+    path
+}
+
+/// Load the config file, falling back to the defaults if there is none.
+pub fn load() -> Result<Config, String> {
+    match std::fs::read_to_string(path()) {
+        Ok(v) => parse(&v),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub fn parse(content: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = match line.split_once('#') {
+            Some((v, _)) => v.trim(),
+            None => line.trim(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let (key, val) = match line.split_once('=') {
+            Some((k, v)) => (k.trim(), v.trim()),
+            None => return Err(format!("config line {}: expected `key = value`", i + 1)),
+        };
+        let fn_invalid = || format!("config line {}: invalid value for `{}`", i + 1, key);
+        match key {
+            "backups" => config.backups = val.parse().map_err(|_| fn_invalid())?,
+            "agent_timeout" => config.agent_timeout = val.parse().map_err(|_| fn_invalid())?,
+            "clipboard_timeout" => {
+                config.clipboard_timeout = val.parse().map_err(|_| fn_invalid())?
+            }
+            "lock_after" => config.lock_after = val.parse().map_err(|_| fn_invalid())?,
+            "breach_db" if !val.is_empty() => config.breach_db = Some(val.to_string()),
+            "breach_db" => return Err(fn_invalid()),
+            _ => return Err(format!("config line {}: unknown key `{}`", i + 1, key)),
+        }
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Config::default(), parse("").expect("parsing"));
+
+        let config = parse("# comment\n\nbackups = 3 # inline\n").expect("parsing");
+        assert_eq!(3, config.backups);
+        assert_eq!(Config::default().agent_timeout, config.agent_timeout);
+
+        let config = parse("agent_timeout = 60").expect("parsing");
+        assert_eq!(60, config.agent_timeout);
+
+        let config = parse("clipboard_timeout = 0").expect("parsing");
+        assert_eq!(0, config.clipboard_timeout);
+
+        let config = parse("lock_after = 60").expect("parsing");
+        assert_eq!(60, config.lock_after);
+
+        let config = parse("breach_db = /srv/hibp # sorted").expect("parsing");
+        assert_eq!(Some("/srv/hibp".to_string()), config.breach_db);
+
+        assert_eq!(
+            "config line 1: invalid value for `backups`",
+            parse("backups = many").unwrap_err()
+        );
+        assert_eq!(
+            "config line 2: unknown key `nope`",
+            parse("backups = 1\nnope = 1").unwrap_err()
+        );
+        assert_eq!(
+            "config line 1: expected `key = value`",
+            parse("backups").unwrap_err()
+        );
+    }
+}
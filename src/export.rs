@@ -0,0 +1,137 @@
+use clap::ValueEnum;
+
+use crate::resource::{self, Instance};
+use crate::secret::Secret;
+
+/// What `onepass export` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// The decrypted file as it is, readable by `onepass import --format onepass`
+    Json,
+    /// One row per resource, custom fields as `name: value` lines in one column
+    Csv,
+    /// The resources encrypted under a passphrase of their own
+    Archive,
+}
+
+impl Format {
+    /// Whether the export holds the passwords unencrypted.
+    pub fn is_plaintext(&self) -> bool {
+        !matches!(self, Format::Archive)
+    }
+}
+
+/// The header row of `csv`, which `import` recognizes the export by.
+pub const CSV_COLUMNS: [&str; 8] = [
+    "name", "username", "password", "url", "notes", "tags", "totp", "fields",
+];
+
+pub fn json(resources: &[Instance]) -> Result<Secret, String> {
+    resource::serialize(resources)
+}
+
+pub fn csv(resources: &[Instance]) -> Result<Secret, String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    if let Err(err) = writer.write_record(CSV_COLUMNS) {
+        return Err(err.to_string());
+    }
+    for v in resources {
+        let fields = Secret::new(
+            v.fields
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, v.expose()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        let tags = v.tags.join(",");
+        let row = [
+            v.name.as_str(),
+            v.user.as_str(),
+            v.password.expose(),
+            v.url.as_str(),
+            v.notes.as_str(),
+            tags.as_str(),
+            v.otp.expose(),
+            fields.expose(),
+        ];
+        if let Err(err) = writer.write_record(row) {
+            return Err(err.to_string());
+        }
+    }
+    let bytes = match writer.into_inner() {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+    match String::from_utf8(bytes) {
+        Ok(v) => Ok(Secret::new(v)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import;
+
+    #[test]
+    fn test_csv() {
+        let mut fields = std::collections::BTreeMap::new();
+        fields.insert("api".to_string(), Secret::from("k1"));
+        fields.insert("pin".to_string(), Secret::from("1234"));
+        let resources = vec![
+            Instance {
+                name: "github".to_string(),
+                user: "octocat".to_string(),
+                password: Secret::from("p,w\"1"),
+                tags: vec!["code".to_string(), "work".to_string()],
+                fields,
+                ..Default::default()
+            },
+            Instance {
+                name: "mail".to_string(),
+                notes: "two\nlines".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let got = csv(&resources).expect("exporting");
+        assert_eq!(
+            "name,username,password,url,notes,tags,totp,fields
+github,octocat,\"p,w\"\"1\",,,\"code,work\",,\"api: k1\npin: 1234\"
+mail,,,,\"two\nlines\",,,
+",
+            got.expose()
+        );
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut fields = std::collections::BTreeMap::new();
+        fields.insert("api".to_string(), Secret::from("k1"));
+        let resources = vec![Instance {
+            name: "github".to_string(),
+            user: "octocat".to_string(),
+            password: Secret::from(" p,w\"1"),
+            url: "https://github.com".to_string(),
+            notes: "two\nlines".to_string(),
+            tags: vec!["code".to_string(), "work".to_string()],
+            otp: Secret::from("JBSWY3DPEHPK3PXP"),
+            fields,
+            ..Default::default()
+        }];
+        let got = csv(&resources).expect("exporting");
+        let imported = import::parse(import::Format::Onepass, got.expose()).expect("parsing");
+        assert_eq!(resources, imported);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let resources = vec![Instance {
+            name: "github".to_string(),
+            password: Secret::from("pw"),
+            ..Default::default()
+        }];
+        let got = json(&resources).expect("exporting");
+        assert_eq!(resources, resource::parse(got.expose()).expect("parsing"));
+    }
+}
@@ -1,29 +1,101 @@
 use std::env;
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::SeekFrom;
-use std::io::{self, Read, Seek, Write};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::str;
+use std::time::{Duration, Instant};
 
 use chacha20poly1305::AeadCore;
 use chacha20poly1305::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     ChaCha20Poly1305, Nonce,
 };
 use rand::rngs::OsRng;
+use zeroize::Zeroizing;
+
+use crate::backup;
+use crate::config;
+use crate::header::{self, Header};
+use crate::kdf::{self, Credential};
+use crate::secret::Secret;
 
 pub const DEFAULT_DIR_NAME: &str = ".onepass";
 pub const DEFAULT_FILE_NAME: &str = "main.txt";
 
+const NONCE_LEN: usize = 12;
+
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Remove the file along with its lock file.
 pub fn purge(custom: Option<&str>) -> io::Result<()> {
-    std::fs::remove_file(path(custom))
+    std::fs::remove_file(path(custom))?;
+    match std::fs::remove_file(lock_path(custom)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Exclusive advisory lock on a file, released when dropped.
+pub struct Lock {
+    _file: File,
+}
+
+/// Take the exclusive lock of the file, waiting up to `LOCK_TIMEOUT`
+/// for other onepass processes to release it.
+/// Hold it across a whole decrypt, modify and encrypt cycle.
+pub fn lock(custom: Option<&str>) -> Result<Lock, String> {
+    lock_with_timeout(custom, LOCK_TIMEOUT)
+}
+
+fn lock_with_timeout(custom: Option<&str>, timeout: Duration) -> Result<Lock, String> {
+    let path = lock_path(custom);
+    if let Some(parent_dir) = path.parent() {
+        if let Err(err) = std::fs::create_dir_all(parent_dir) {
+            return Err(err.to_string());
+        }
+    }
+    // The lock lives in a separate file because the vault itself is
+    // replaced by a rename on every write.
+    let file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(&path)
+    {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let started = Instant::now();
+    loop {
+        // SAFETY: the descriptor is valid for as long as `file` lives.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(Lock { _file: file });
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(err.to_string());
+        }
+        if started.elapsed() >= timeout {
+            return Err("file is in use by another onepass process - try again".to_string());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn lock_path(custom: Option<&str>) -> PathBuf {
+    let mut path = path(custom).into_os_string();
+    path.push(".lock");
+    PathBuf::from(path)
 }
 
 /// Create the needed file for the application.
 /// The path can be adjusted with parameters.
 pub fn create(custom_path: Option<&str>) -> io::Result<std::fs::File> {
-    let ver = "0.3";
     let path = path(custom_path);
 
     if let Some(parent_dir) = path.parent() {
@@ -33,6 +105,7 @@ pub fn create(custom_path: Option<&str>) -> io::Result<std::fs::File> {
     let file = OpenOptions::new()
         .write(true)
         .create_new(true)
+        .mode(0o600)
         .open(&path)?;
 
     match custom_path {
@@ -41,8 +114,8 @@ pub fn create(custom_path: Option<&str>) -> io::Result<std::fs::File> {
         }
         None => {
             println!(
-                "Initialized file at ~/{}/{} {}",
-                DEFAULT_DIR_NAME, DEFAULT_FILE_NAME, ver
+                "Initialized file at ~/{}/{}",
+                DEFAULT_DIR_NAME, DEFAULT_FILE_NAME
             )
         }
     }
@@ -50,16 +123,42 @@ pub fn create(custom_path: Option<&str>) -> io::Result<std::fs::File> {
     Ok(file)
 }
 
-pub fn open_truncate(custom: Option<&str>) -> io::Result<std::fs::File> {
+/// Replace the file with the given bytes without ever leaving it half written.
+/// The bytes go to a sibling temp file which is fsynced and then renamed over
+/// the original, so the file is always either the old or the new version.
+pub fn write_atomic(custom: Option<&str>, bytes: &[u8]) -> io::Result<()> {
     let path = path(custom);
-
-    let file = OpenOptions::new()
-        .read(true)
-        .truncate(true)
-        .write(true)
-        .open(&path)?;
-
-    Ok(file)
+    let dir = match path.parent() {
+        Some(v) => v.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    let file_name = match path.file_name() {
+        Some(v) => v.to_string_lossy().to_string(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path does not name a file",
+            ))
+        }
+    };
+    let tmp_path = dir.join(format!(".{}.tmp-{}", file_name, std::process::id()));
+
+    let result = (|| {
+        let mut tmp = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        tmp.write_all(bytes)?;
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+        // Persist the rename itself.
+        File::open(&dir)?.sync_all()
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
 }
 
 pub fn exists(custom: Option<&str>) -> bool {
@@ -67,59 +166,231 @@ pub fn exists(custom: Option<&str>) -> bool {
     Path::new(&path).exists()
 }
 
+/// Encrypt the content and write it to the file.
+/// The salt and KDF parameters of an existing vault are kept,
+/// a new vault (or one in an older format) gets a fresh current header.
 pub fn encrypt(
+    custom_path: Option<&str>,
+    credential: &Credential,
+    content: Secret,
+) -> Result<Vec<u8>, String> {
+    let header = match read_header(custom_path) {
+        Some(v) if v.is_current() => v,
+        _ => Header::for_credential(credential),
+    };
+    encrypt_with(custom_path, credential, content, &header)
+}
+
+/// Encrypt the content under a new password with a fresh salt and header,
+/// e.g when the master password changes.
+pub fn rekey(
     custom_path: Option<&str>,
     password: &str,
-    content: String,
+    content: Secret,
 ) -> Result<Vec<u8>, String> {
-    let h = hmac_sha256::Hash::hash(password.as_bytes());
+    let credential = Credential::Password(Secret::from(password));
+    encrypt_with(custom_path, &credential, content, &Header::new())
+}
+
+/// Re-encrypt the backups of the file that `old` opens with `new`, e.g
+/// once the master password changed, so the old one no longer opens them.
+/// Returns how many backups `old` did not open, which are left as they are.
+pub fn rekey_backups(
+    custom_path: Option<&str>,
+    old: &Credential,
+    new: &Credential,
+) -> Result<usize, String> {
+    let entries = match backup::list(custom_path) {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+    let header = Header::for_credential(new);
+    let mut left = 0;
+    for entry in entries {
+        let path = entry.path.to_string_lossy().to_string();
+        let content = match decrypt_with_header(Some(&path), old) {
+            Ok((v, _, _)) => v,
+            Err(_) => {
+                left += 1;
+                continue;
+            }
+        };
+        let (bytes, _) = seal_with(new, &content, &header)?;
+        if let Err(err) = write_atomic(Some(&path), &bytes) {
+            return Err(format!("{}: {}", path, err));
+        }
+    }
+    Ok(left)
+}
+
+fn encrypt_with(
+    custom_path: Option<&str>,
+    credential: &Credential,
+    content: Secret,
+    header: &Header,
+) -> Result<Vec<u8>, String> {
+    let (bytes, ciphertext) = seal_with(credential, &content, header)?;
+    let retention = config::load()?.backups;
+    if let Err(err) = backup::create(custom_path, retention) {
+        return Err(format!("backing up: {}", err));
+    }
+    if let Err(err) = write_atomic(custom_path, &bytes) {
+        return Err(err.to_string());
+    }
+
+    Ok(ciphertext)
+}
+
+/// Encrypt the content the way the file is, under its own password and a
+/// fresh header, without writing it anywhere. E.g for a portable archive.
+pub fn seal(password: &str, content: &Secret) -> Result<Vec<u8>, String> {
+    let credential = Credential::Password(Secret::from(password));
+    let (bytes, _) = seal_with(&credential, content, &Header::new())?;
+    Ok(bytes)
+}
+
+/// Decrypt what `seal` returned.
+pub fn unseal(bytes: &[u8], password: &str) -> Result<Secret, String> {
+    if !is_sealed(bytes) {
+        return Err("Not a onepass archive".to_string());
+    }
+    let credential = Credential::Password(Secret::from(password));
+    let (content, _, _) = open_data(parse_data(bytes)?, &credential)?;
+    Ok(content)
+}
+
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(header::MAGIC)
+}
+
+/// The bytes of the file and the ciphertext in them.
+fn seal_with(
+    credential: &Credential,
+    content: &Secret,
+    header: &Header,
+) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let key = header.derive(credential)?;
+    let header_bytes = header.to_bytes();
 
     let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
-    let cipher = match ChaCha20Poly1305::new_from_slice(&h) {
+    let cipher = match ChaCha20Poly1305::new_from_slice(&key[..]) {
         Ok(c) => c,
         Err(err) => {
             return Err(err.to_string());
         }
     };
-    let ciphertext = match cipher.encrypt(&nonce, content.as_ref()) {
+    let payload = Payload {
+        msg: content.expose().as_bytes(),
+        aad: &header_bytes,
+    };
+    let ciphertext = match cipher.encrypt(&nonce, payload) {
         Ok(v) => v,
         Err(err) => return Err(err.to_string()),
     };
 
-    let mut f = match open_truncate(custom_path) {
+    let mut bytes = header_bytes;
+    bytes.extend_from_slice(nonce.as_slice());
+    bytes.extend_from_slice(ciphertext.as_slice());
+    Ok((bytes, ciphertext))
+}
+
+/// Decrypt the file and return its content.
+/// Vaults in an older format are re-encrypted in the current one on the way out.
+pub fn decrypt(path: Option<&str>, credential: &Credential) -> Result<Secret, String> {
+    let (content, header, _) = decrypt_with_header(path, credential)?;
+
+    if !header.is_current() {
+        upgrade(path, credential)?;
+    }
+
+    Ok(content)
+}
+
+/// Re-encrypt a vault in an older format in the current one, unless the
+/// lock is taken. Whoever holds it, this process included, writes the
+/// current format anyway, and otherwise the next command upgrades it.
+fn upgrade(path: Option<&str>, credential: &Credential) -> Result<(), String> {
+    let _lock = match lock_with_timeout(path, Duration::ZERO) {
         Ok(v) => v,
-        Err(err) => return Err(err.to_string()),
+        Err(_) => return Ok(()),
     };
-    if let Err(err) = f.write_all(nonce.as_slice()) {
-        return Err(err.to_string());
+    // The file may have changed since it was read without the lock.
+    let (content, header, _) = decrypt_with_header(path, credential)?;
+    if !header.is_current() {
+        let new_header = Header::for_credential(credential);
+        encrypt_with(path, credential, content, &new_header)?;
     }
-    if let Err(err) = f.write_all(ciphertext.as_slice()) {
-        return Err(err.to_string());
+    Ok(())
+}
+
+/// Check that the file decrypts with the credential, without ever writing to it.
+pub fn check(path: Option<&str>, credential: &Credential) -> Result<(), String> {
+    decrypt_with_header(path, credential)?;
+    Ok(())
+}
+
+/// Check the password against the file and return the key derived from it,
+/// which unlocks the file from then on without deriving it again.
+pub fn unlock(path: Option<&str>, password: &str) -> Result<Credential, String> {
+    let credential = Credential::Password(Secret::from(password));
+    let (_, header, key) = decrypt_with_header(path, &credential)?;
+    if header.is_current() {
+        return Ok(Credential::Key(derived(&header, &key)));
     }
 
-    Ok(ciphertext)
+    // Upgrading writes the file, so it waits for other writers and reads
+    // the file again once they are done.
+    let _lock = lock(path)?;
+    let (content, mut header, mut key) = decrypt_with_header(path, &credential)?;
+    if !header.is_current() {
+        header = Header::new();
+        key = header.derive(&credential)?;
+        encrypt_with(
+            path,
+            &Credential::Key(derived(&header, &key)),
+            content,
+            &header,
+        )?;
+    }
+
+    Ok(Credential::Key(derived(&header, &key)))
 }
 
-pub fn decrypt(path: Option<&str>, password: &str) -> Result<String, String> {
-    let h = hmac_sha256::Hash::hash(password.as_bytes());
+fn derived(header: &Header, key: &kdf::Key) -> kdf::DerivedKey {
+    kdf::DerivedKey {
+        salt: header.salt.clone(),
+        params: header.params,
+        key: key.clone(),
+    }
+}
+
+fn decrypt_with_header(
+    path: Option<&str>,
+    credential: &Credential,
+) -> Result<(Secret, Header, kdf::Key), String> {
+    let mut f = match open(path) {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+    let data = extract_data(&mut f)?;
+    open_data(data, credential)
+}
 
-    let cipher = match ChaCha20Poly1305::new_from_slice(&h) {
+fn open_data(data: Data, credential: &Credential) -> Result<(Secret, Header, kdf::Key), String> {
+    let key = data.header.derive(credential)?;
+    let cipher = match ChaCha20Poly1305::new_from_slice(&key[..]) {
         Ok(c) => c,
         Err(err) => {
             return Err(err.to_string());
         }
     };
 
-    let mut f = match open(path) {
-        Ok(v) => v,
-        Err(err) => return Err(err.to_string()),
-    };
-    let data = match extract_data(&mut f) {
-        Ok(v) => v,
-        Err(err) => return Err(err.to_string()),
+    let payload = Payload {
+        msg: data.buf.as_ref(),
+        aad: &data.aad,
     };
-    let plaintext = match cipher.decrypt(&data.nonce, data.buf.as_ref()) {
-        Ok(v) => v,
+    let plaintext = match cipher.decrypt(&data.nonce, payload) {
+        Ok(v) => Zeroizing::new(v),
         Err(err) => {
             let err_str = err.to_string();
             if err_str == "aead::Error" {
@@ -130,7 +401,7 @@ pub fn decrypt(path: Option<&str>, password: &str) -> Result<String, String> {
         }
     };
     match std::str::from_utf8(&plaintext) {
-        Ok(v) => Ok(v.to_string()),
+        Ok(v) => Ok((Secret::from(v), data.header, key)),
         Err(err) => Err(err.to_string()),
     }
 }
@@ -148,21 +419,53 @@ pub fn path(custom_path: Option<&str>) -> PathBuf {
 }
 
 pub struct Data {
+    pub header: Header,
+    /// Associated data the ciphertext is bound to: the raw header bytes
+    /// for the current format, empty for older ones.
+    pub aad: Vec<u8>,
     pub nonce: Nonce,
     pub buf: Vec<u8>,
 }
 
-fn extract_data(f: &mut File) -> Result<Data, io::Error> {
-    let mut nonce_buf = [0u8; 12];
-    f.read_exact(&mut nonce_buf)?;
+fn extract_data(f: &mut File) -> Result<Data, String> {
+    let mut raw = Vec::new();
+    if let Err(err) = f.read_to_end(&mut raw) {
+        return Err(err.to_string());
+    }
+    parse_data(&raw)
+}
 
-    let nonce = *Nonce::from_slice(&nonce_buf);
+fn parse_data(raw: &[u8]) -> Result<Data, String> {
+    let (header, offset) = header::parse(raw)?;
+    if raw.len() < offset + NONCE_LEN {
+        return Err("file is too short".to_string());
+    }
 
-    f.seek(SeekFrom::Start(12))?;
-    let mut buf = Vec::new();
-    f.read_to_end(&mut buf)?;
+    // Legacy vaults have no header to authenticate.
+    let aad = match header.version {
+        0 => vec![],
+        _ => raw[..offset].to_vec(),
+    };
+    let nonce = *Nonce::from_slice(&raw[offset..offset + NONCE_LEN]);
+    let buf = raw[offset + NONCE_LEN..].to_vec();
+
+    Ok(Data {
+        header,
+        aad,
+        nonce,
+        buf,
+    })
+}
 
-    Ok(Data { nonce, buf })
+/// Read the header of an existing vault, if it has one.
+fn read_header(custom: Option<&str>) -> Option<Header> {
+    let mut f = open(custom).ok()?;
+    let mut raw = Vec::new();
+    f.read_to_end(&mut raw).ok()?;
+    match header::parse(&raw) {
+        Ok((v, len)) if len > 0 => Some(v),
+        _ => None,
+    }
 }
 
 fn open(custom: Option<&str>) -> io::Result<std::fs::File> {
@@ -178,6 +481,10 @@ mod tests {
     use super::*;
     use uuid::Uuid;
 
+    fn credential(password: &str) -> Credential {
+        Credential::Password(Secret::from(password))
+    }
+
     struct Cleanup {
         file_name: String,
     }
@@ -193,6 +500,9 @@ mod tests {
     impl Drop for Cleanup {
         fn drop(&mut self) {
             let file_path = format!("{}/{}.txt", DEFAULT_DIR_NAME, self.file_name);
+            for entry in backup::list(Some(&file_path)).expect("listing backups") {
+                std::fs::remove_file(entry.path).expect("cleaning up backup");
+            }
             purge(Some(&file_path)).expect("cleaning up");
         }
     }
@@ -216,15 +526,33 @@ mod tests {
         let t_path = &cleanup.path();
         create(Some(t_path)).expect("creating");
         let c = "content\ndelimiter\nsecret-stuff\n";
-        encrypt(Some(t_path), "master_pw", c.to_string()).expect("encrypting");
+        encrypt(Some(t_path), &credential("master_pw"), Secret::from(c)).expect("encrypting");
 
         let mut o = open(Some(t_path)).expect("opening");
         let data = extract_data(&mut o).expect("extracting");
 
-        assert!(data.buf.len() > 0);
+        assert!(!data.buf.is_empty());
+        assert!(data.header.is_current());
+        assert!(!data.aad.is_empty());
         assert_eq!(data.nonce.len(), 12);
     }
 
+    #[test]
+    fn test_seal() {
+        let content = Secret::from("content");
+        let sealed = seal("archive_pw", &content).expect("sealing");
+        assert_eq!(content, unseal(&sealed, "archive_pw").expect("unsealing"));
+        assert_eq!(
+            "Incorrect password - aborting.",
+            unseal(&sealed, "wrong").unwrap_err()
+        );
+        assert!(is_sealed(&sealed));
+        assert_eq!(
+            "Not a onepass archive",
+            unseal(b"content", "archive_pw").unwrap_err()
+        );
+    }
+
     #[test]
     fn test_encrypt_decrypt() {
         let id = Uuid::new_v4();
@@ -236,11 +564,199 @@ mod tests {
 
         let content = "content\ndelimiter\nsecret-stuff\n";
         let pw = "masterPassword";
-        encrypt(Some(t_path), pw, content.to_string()).expect("encrypting");
+        encrypt(Some(t_path), &credential(pw), Secret::from(content)).expect("encrypting");
+
+        let decrypted_content = decrypt(Some(t_path), &credential(pw)).expect("decrypting");
+
+        assert_eq!(content, decrypted_content.expose());
+    }
+
+    #[test]
+    fn test_encrypt_keeps_salt() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        create(Some(t_path)).expect("creating");
+
+        let pw = "masterPassword";
+        encrypt(Some(t_path), &credential(pw), Secret::from("first")).expect("encrypting");
+        let first = read_header(Some(t_path)).expect("reading header");
+        encrypt(Some(t_path), &credential(pw), Secret::from("second")).expect("encrypting");
+        let second = read_header(Some(t_path)).expect("reading header");
+
+        assert_eq!(first.salt, second.salt);
+        assert_eq!(first.params, second.params);
+        assert_eq!(
+            "second",
+            decrypt(Some(t_path), &credential(pw))
+                .expect("decrypting")
+                .expose()
+        );
+    }
+
+    #[test]
+    fn test_decrypt_upgrades_legacy() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        let mut f = create(Some(t_path)).expect("creating");
+
+        let content = "resource\nname\nuser\npassword\n";
+        let pw = "masterPassword";
+        let cipher = ChaCha20Poly1305::new_from_slice(crate::kdf::derive_legacy(pw).as_ref())
+            .expect("cipher");
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, content.as_bytes())
+            .expect("encrypting");
+        f.write_all(nonce.as_slice()).expect("writing nonce");
+        f.write_all(&ciphertext).expect("writing ciphertext");
+        assert!(read_header(Some(t_path)).is_none());
+
+        assert!(decrypt(Some(t_path), &credential("wrong")).is_err());
+        assert!(read_header(Some(t_path)).is_none());
+
+        // Whoever holds the lock may be about to write the file.
+        let held = lock(Some(t_path)).expect("locking");
+        assert_eq!(
+            content,
+            decrypt(Some(t_path), &credential(pw))
+                .expect("decrypting")
+                .expose()
+        );
+        assert!(read_header(Some(t_path)).is_none());
+        drop(held);
+
+        assert_eq!(
+            content,
+            decrypt(Some(t_path), &credential(pw))
+                .expect("decrypting")
+                .expose()
+        );
+        assert!(read_header(Some(t_path)).expect("header").is_current());
+        assert_eq!(
+            content,
+            decrypt(Some(t_path), &credential(pw))
+                .expect("decrypting")
+                .expose()
+        );
+    }
+
+    #[test]
+    fn test_decrypt_unknown_version() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        create(Some(t_path)).expect("creating");
+        encrypt(Some(t_path), &credential("pw"), Secret::from("content")).expect("encrypting");
+
+        let mut raw = std::fs::read(path(Some(t_path))).expect("reading");
+        raw[header::MAGIC.len()] = header::VERSION + 1;
+        std::fs::write(path(Some(t_path)), raw).expect("writing");
+
+        let err = decrypt(Some(t_path), &credential("pw")).unwrap_err();
+        assert!(err.contains("not supported"));
+    }
+
+    #[test]
+    fn test_decrypt_rejects_oversized_params() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        create(Some(t_path)).expect("creating");
+
+        let mut header = Header::new();
+        header.params.m_cost = kdf::Params::MAX.m_cost + 1;
+        let mut raw = header.to_bytes();
+        raw.extend_from_slice(&[0u8; NONCE_LEN + 32]);
+        std::fs::write(path(Some(t_path)), raw).expect("writing");
+
+        // Deriving would fail on the password, not the parameters.
+        let err = decrypt(Some(t_path), &credential("pw")).unwrap_err();
+        assert!(err.contains("out of range"), "{}", err);
+    }
+
+    #[test]
+    fn test_unlock() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        create(Some(t_path)).expect("creating");
+        let pw = "masterPassword";
+        encrypt(Some(t_path), &credential(pw), Secret::from("first")).expect("encrypting");
+
+        assert!(unlock(Some(t_path), "wrong").is_err());
+        let key = unlock(Some(t_path), pw).expect("unlocking");
+        assert_eq!(
+            "first",
+            decrypt(Some(t_path), &key).expect("decrypting").expose()
+        );
+        encrypt(Some(t_path), &key, Secret::from("second")).expect("encrypting");
+        assert_eq!(
+            "second",
+            decrypt(Some(t_path), &credential(pw))
+                .expect("decrypting")
+                .expose()
+        );
+
+        // A key does not unlock the file any more once the password changes.
+        rekey(Some(t_path), "newPassword", Secret::from("third")).expect("rekeying");
+        assert_eq!(
+            "key does not belong to this file",
+            decrypt(Some(t_path), &key).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        create(Some(t_path)).expect("creating");
+
+        write_atomic(Some(t_path), b"first").expect("writing");
+        write_atomic(Some(t_path), b"second").expect("writing");
+        assert_eq!(
+            b"second".to_vec(),
+            std::fs::read(path(Some(t_path))).expect("reading")
+        );
+
+        let dir = path(Some(DEFAULT_DIR_NAME));
+        let leftovers = std::fs::read_dir(dir)
+            .expect("reading dir")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().contains(&id.to_string()))
+            .count();
+        assert_eq!(1, leftovers);
+    }
+
+    #[test]
+    fn test_lock() {
+        let id = Uuid::new_v4();
+        let cleanup = Cleanup {
+            file_name: id.to_string(),
+        };
+        let t_path = &cleanup.path();
+        create(Some(t_path)).expect("creating");
 
-        let decrypted_content = decrypt(Some(t_path), pw).expect("decrypting");
+        let held = lock(Some(t_path)).expect("locking");
+        let err = lock_with_timeout(Some(t_path), Duration::from_millis(100));
+        assert!(err.is_err());
 
-        assert_eq!(content, decrypted_content);
+        drop(held);
+        lock_with_timeout(Some(t_path), Duration::from_millis(100)).expect("relocking");
     }
 
     #[test]
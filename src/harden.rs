@@ -0,0 +1,171 @@
+use std::alloc::{self, Layout};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Keep secrets out of core dumps, swap and debuggers:
+///
+/// - `PR_SET_DUMPABLE=0` stops core dumps and makes the kernel refuse
+///   ptrace attaches from other processes of the same user, Linux only.
+/// - `RLIMIT_CORE=0` stops core dumps even if something resets the flag.
+/// - key material lives in `Locked` buffers, which are kept out of swap.
+///
+/// None of these are fatal when they fail, `onepass doctor` shows which
+/// ones are active.
+pub fn apply() {
+    ENABLED.store(true, Ordering::Relaxed);
+    // SAFETY: plain syscalls with valid arguments.
+    unsafe {
+        #[cfg(target_os = "linux")]
+        libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        libc::setrlimit(libc::RLIMIT_CORE, &limit);
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether the process can be dumped or attached to, `None` where the
+/// flag does not exist.
+#[cfg(target_os = "linux")]
+pub fn dumpable() -> Option<bool> {
+    // SAFETY: PR_GET_DUMPABLE takes no arguments.
+    Some(unsafe { libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0) != 0 })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn dumpable() -> Option<bool> {
+    None
+}
+
+/// The soft limit on core file size, `None` if unlimited.
+pub fn core_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid out pointer.
+    if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) } != 0 {
+        return None;
+    }
+    match limit.rlim_cur {
+        libc::RLIM_INFINITY => None,
+        v => Some(v),
+    }
+}
+
+/// The Yama ptrace scope, 0 lets any process of the user attach.
+pub fn ptrace_scope() -> Option<u8> {
+    let scope = std::fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope").ok()?;
+    scope.trim().parse().ok()
+}
+
+/// Bytes kept on their own heap pages, locked out of swap and wiped on drop.
+/// Page locks are not counted, so no other allocation may share the pages
+/// that are unlocked again on drop.
+/// Locking is skipped without hardening and may fail, e.g on RLIMIT_MEMLOCK.
+pub struct Locked<const N: usize> {
+    bytes: NonNull<[u8; N]>,
+    layout: Layout,
+    locked: bool,
+}
+
+// SAFETY: `Locked` owns its allocation like a `Box` does.
+unsafe impl<const N: usize> Send for Locked<N> {}
+// SAFETY: shared references only hand out `&[u8; N]`.
+unsafe impl<const N: usize> Sync for Locked<N> {}
+
+impl<const N: usize> Locked<N> {
+    /// A zeroed buffer, fill it in place so no copy of the secret is left
+    /// on the stack.
+    pub fn zeroed() -> Locked<N> {
+        // SAFETY: sysconf has no preconditions.
+        let page = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            v if v > 0 => v as usize,
+            _ => 4096,
+        };
+        let layout = Layout::from_size_align(N.max(1).next_multiple_of(page), page)
+            .expect("page sized layout");
+        // SAFETY: the layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let bytes = match NonNull::new(ptr.cast::<[u8; N]>()) {
+            Some(v) => v,
+            None => alloc::handle_alloc_error(layout),
+        };
+        // SAFETY: the pointer and length describe the allocation.
+        let locked = enabled() && unsafe { libc::mlock(ptr.cast(), layout.size()) } == 0;
+        Locked {
+            bytes,
+            layout,
+            locked,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl<const N: usize> Clone for Locked<N> {
+    fn clone(&self) -> Self {
+        let mut clone = Locked::zeroed();
+        clone.copy_from_slice(&self[..]);
+        clone
+    }
+}
+
+impl<const N: usize> Deref for Locked<N> {
+    type Target = [u8; N];
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the allocation is owned, initialized and lives as long as `self`.
+        unsafe { self.bytes.as_ref() }
+    }
+}
+
+impl<const N: usize> DerefMut for Locked<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: as in `deref`, and `&mut self` makes the access unique.
+        unsafe { self.bytes.as_mut() }
+    }
+}
+
+impl<const N: usize> Drop for Locked<N> {
+    fn drop(&mut self) {
+        for b in self.iter_mut() {
+            // SAFETY: `b` is a valid, aligned reference into the array.
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+        let ptr = self.bytes.as_ptr().cast::<u8>();
+        // SAFETY: the pages were allocated in `zeroed` with `self.layout`
+        // and are not shared with any other allocation.
+        unsafe {
+            if self.locked {
+                libc::munlock(ptr.cast(), self.layout.size());
+            }
+            alloc::dealloc(ptr, self.layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locked() {
+        let mut locked = Locked::<4>::zeroed();
+        assert_eq!([0u8; 4], *locked);
+        locked.copy_from_slice(&[7u8; 4]);
+        locked[0] = 1;
+        assert_eq!([1, 7, 7, 7], *locked.clone());
+        assert_eq!(0, locked.as_ptr() as usize % 4096);
+    }
+}
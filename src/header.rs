@@ -0,0 +1,302 @@
+use crate::kdf;
+
+/// Every vault written with a header starts with these bytes.
+/// Files without them are legacy vaults (format version 0).
+pub const MAGIC: &[u8; 7] = b"ONEPASS";
+pub const VERSION: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn id(&self) -> u8 {
+        match self {
+            Cipher::ChaCha20Poly1305 => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Cipher, String> {
+        match id {
+            1 => Ok(Cipher::ChaCha20Poly1305),
+            _ => Err(format!("unsupported cipher id {}", id)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// Bare SHA-256 of the master password, only found in legacy vaults.
+    Sha256,
+    Argon2id,
+}
+
+impl Kdf {
+    fn id(&self) -> u8 {
+        match self {
+            Kdf::Sha256 => 0,
+            Kdf::Argon2id => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Kdf, String> {
+        match id {
+            0 => Ok(Kdf::Sha256),
+            1 => Ok(Kdf::Argon2id),
+            _ => Err(format!("unsupported kdf id {}", id)),
+        }
+    }
+}
+
+/// Self-describing file header.
+///
+/// Format version 2 layout, integers little endian:
+///
+/// ```text
+/// magic (7) | version (1) | header length (2) | cipher id (1) | kdf id (1)
+/// | salt length (1) | salt | m_cost (4) | t_cost (4) | p_cost (4)
+/// ```
+///
+/// The header is followed by the nonce and the ciphertext. The raw header
+/// bytes are passed to the cipher as associated data, so any tampering with
+/// them fails authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: Cipher,
+    pub kdf: Kdf,
+    pub salt: Vec<u8>,
+    pub params: kdf::Params,
+}
+
+impl Header {
+    /// A current version header with a fresh salt and the default parameters.
+    pub fn new() -> Header {
+        Header {
+            version: VERSION,
+            cipher: Cipher::ChaCha20Poly1305,
+            kdf: Kdf::Argon2id,
+            salt: kdf::salt().to_vec(),
+            params: kdf::Params::default(),
+        }
+    }
+
+    /// A current version header for a new file. A derived key brings
+    /// its own salt and parameters, a password gets fresh ones.
+    pub fn for_credential(credential: &kdf::Credential) -> Header {
+        match credential {
+            kdf::Credential::Password(_) => Header::new(),
+            kdf::Credential::Key(k) => Header {
+                salt: k.salt.clone(),
+                params: k.params,
+                ..Header::new()
+            },
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.version == VERSION
+    }
+
+    /// Get the cipher key as described by the header, either by deriving it
+    /// from the password or by checking that a derived key belongs to it.
+    pub fn derive(&self, credential: &kdf::Credential) -> Result<kdf::Key, String> {
+        match (credential, self.kdf) {
+            (kdf::Credential::Password(p), Kdf::Sha256) => Ok(kdf::derive_legacy(p.expose())),
+            (kdf::Credential::Password(p), Kdf::Argon2id) => {
+                kdf::derive(p.expose(), &self.salt, self.params)
+            }
+            (kdf::Credential::Key(k), Kdf::Argon2id)
+                if k.salt == self.salt && k.params == self.params =>
+            {
+                Ok(k.key.clone())
+            }
+            (kdf::Credential::Key(_), _) => Err("key does not belong to this file".to_string()),
+        }
+    }
+
+    /// Serialize the header in the current format version.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        // Placeholder for the header length, filled in below.
+        buf.extend_from_slice(&[0, 0]);
+        buf.push(self.cipher.id());
+        buf.push(self.kdf.id());
+        buf.push(self.salt.len() as u8);
+        buf.extend_from_slice(&self.salt);
+        buf.extend_from_slice(&self.params.m_cost.to_le_bytes());
+        buf.extend_from_slice(&self.params.t_cost.to_le_bytes());
+        buf.extend_from_slice(&self.params.p_cost.to_le_bytes());
+
+        let len = (buf.len() as u16).to_le_bytes();
+        buf[MAGIC.len() + 1] = len[0];
+        buf[MAGIC.len() + 2] = len[1];
+        buf
+    }
+}
+
+/// Parse the header at the start of the buffer.
+/// Returns the header and its length in bytes, which is 0 for legacy vaults.
+pub fn parse(buf: &[u8]) -> Result<(Header, usize), String> {
+    if buf.len() < MAGIC.len() + 1 || &buf[..MAGIC.len()] != MAGIC {
+        return Ok((
+            Header {
+                version: 0,
+                cipher: Cipher::ChaCha20Poly1305,
+                kdf: Kdf::Sha256,
+                salt: vec![],
+                params: kdf::Params::default(),
+            },
+            0,
+        ));
+    }
+
+    let mut reader = Reader {
+        buf,
+        offset: MAGIC.len(),
+    };
+    let version = reader.u8()?;
+    match version {
+        VERSION => parse_current(&mut reader),
+        _ => Err(format!(
+            "file format version {} is not supported by this version of onepass - please upgrade",
+            version
+        )),
+    }
+}
+
+fn parse_current(reader: &mut Reader) -> Result<(Header, usize), String> {
+    let len = reader.u16()? as usize;
+    let cipher = Cipher::from_id(reader.u8()?)?;
+    let kdf = Kdf::from_id(reader.u8()?)?;
+    let salt_len = reader.u8()? as usize;
+    let salt = reader.bytes(salt_len)?.to_vec();
+    let params = reader.params()?;
+    params.check()?;
+    if len < reader.offset || len > reader.buf.len() {
+        return Err("malformed file header".to_string());
+    }
+    Ok((
+        Header {
+            version: VERSION,
+            cipher,
+            kdf,
+            salt,
+            params,
+        },
+        len,
+    ))
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, n: usize) -> Result<&[u8], String> {
+        if self.offset + n > self.buf.len() {
+            return Err("file header is truncated".to_string());
+        }
+        let v = &self.buf[self.offset..self.offset + n];
+        self.offset += n;
+        Ok(v)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn params(&mut self) -> Result<kdf::Params, String> {
+        Ok(kdf::Params {
+            m_cost: self.u32()?,
+            t_cost: self.u32()?,
+            p_cost: self.u32()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let header = Header::new();
+        let bytes = header.to_bytes();
+        let (parsed, len) = parse(&bytes).expect("parsing");
+        assert_eq!(header, parsed);
+        assert_eq!(bytes.len(), len);
+        assert!(parsed.is_current());
+    }
+
+    #[test]
+    fn test_parse_legacy() {
+        let (parsed, len) = parse(&[7u8; 40]).expect("parsing");
+        assert_eq!(0, parsed.version);
+        assert_eq!(Kdf::Sha256, parsed.kdf);
+        assert_eq!(0, len);
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown() {
+        let mut bytes = Header::new().to_bytes();
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert!(parse(&bytes).unwrap_err().contains("not supported"));
+
+        let mut bytes = Header::new().to_bytes();
+        bytes[MAGIC.len() + 3] = 42;
+        assert_eq!("unsupported cipher id 42", parse(&bytes).unwrap_err());
+
+        let bytes = Header::new().to_bytes();
+        assert_eq!(
+            "file header is truncated",
+            parse(&bytes[..bytes.len() - 1]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_oversized_params() {
+        let max = kdf::Params::MAX;
+        for params in [
+            kdf::Params {
+                m_cost: max.m_cost + 1,
+                ..max
+            },
+            kdf::Params {
+                t_cost: max.t_cost + 1,
+                ..max
+            },
+            kdf::Params {
+                p_cost: max.p_cost + 1,
+                ..max
+            },
+        ] {
+            let header = Header {
+                params,
+                ..Header::new()
+            };
+            let err = parse(&header.to_bytes()).unwrap_err();
+            assert!(err.contains("out of range"), "{}", err);
+        }
+
+        let header = Header {
+            params: max,
+            ..Header::new()
+        };
+        parse(&header.to_bytes()).expect("parsing");
+    }
+}
@@ -0,0 +1,616 @@
+use std::collections::{BTreeMap, HashMap};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::export;
+use crate::resource::{self, Instance};
+use crate::secret::Secret;
+
+/// Exports of other password managers that can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    BitwardenCsv,
+    BitwardenJson,
+    Lastpass,
+    #[value(name = "1password")]
+    OnePassword,
+    Keepassxc,
+    Chrome,
+    Firefox,
+    /// A JSON or CSV export or an archive of onepass itself
+    Onepass,
+}
+
+/// What happens to an imported resource whose name is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Duplicates {
+    /// Keep the existing resource and drop the imported one
+    Skip,
+    /// Import it under a new name, e.g `github (2)`
+    Rename,
+    /// Replace the existing resource with the imported one
+    Replace,
+}
+
+/// What importing did, or would do on a dry run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub added: Vec<String>,
+    /// Old and new name.
+    pub renamed: Vec<(String, String)>,
+    pub replaced: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Read the resources of an export.
+pub fn parse(format: Format, data: &str) -> Result<Vec<Instance>, String> {
+    match format {
+        Format::BitwardenJson => parse_bitwarden_json(data),
+        Format::Onepass if !is_onepass_csv(data) => resource::parse(data),
+        _ => parse_csv(format, data),
+    }
+}
+
+/// Add the imported resources to the existing ones.
+pub fn merge(
+    mut resources: Vec<Instance>,
+    imported: Vec<Instance>,
+    duplicates: Duplicates,
+) -> (Vec<Instance>, Report) {
+    let mut report = Report::default();
+    for mut v in imported {
+        let existing = resources.iter().position(|r| r.name == v.name);
+        match (existing, duplicates) {
+            (None, _) => report.added.push(v.name.clone()),
+            (Some(_), Duplicates::Skip) => {
+                report.skipped.push(v.name);
+                continue;
+            }
+            (Some(i), Duplicates::Replace) => {
+                report.replaced.push(v.name.clone());
+                resources[i] = v;
+                continue;
+            }
+            (Some(_), Duplicates::Rename) => {
+                let name = free_name(&resources, &v.name);
+                report.renamed.push((v.name, name.clone()));
+                v.name = name;
+            }
+        }
+        resources.push(v);
+    }
+    (resources, report)
+}
+
+/// `name (2)`, `name (3)` and so on, whichever is not taken yet.
+fn free_name(resources: &[Instance], name: &str) -> String {
+    (2..)
+        .map(|i| format!("{} ({})", name, i))
+        .find(|v| !resources.iter().any(|r| &r.name == v))
+        .unwrap_or_default()
+}
+
+/// Header names of the columns in each CSV export, matched case-insensitively.
+struct Columns {
+    name: &'static [&'static str],
+    url: &'static [&'static str],
+    user: &'static [&'static str],
+    password: &'static [&'static str],
+    notes: &'static [&'static str],
+    otp: &'static [&'static str],
+    /// Folder or group, imported as tags.
+    tags: &'static [&'static str],
+}
+
+/// The columns of a CSV format, `None` for the others.
+fn columns(format: Format) -> Option<Columns> {
+    let columns = match format {
+        Format::BitwardenJson => return None,
+        Format::BitwardenCsv => Columns {
+            name: &["name"],
+            url: &["login_uri"],
+            user: &["login_username"],
+            password: &["login_password"],
+            notes: &["notes"],
+            otp: &["login_totp"],
+            tags: &["folder"],
+        },
+        Format::Lastpass => Columns {
+            name: &["name"],
+            url: &["url"],
+            user: &["username"],
+            password: &["password"],
+            notes: &["extra"],
+            otp: &["totp"],
+            tags: &["grouping"],
+        },
+        Format::OnePassword => Columns {
+            name: &["title"],
+            url: &["url", "website"],
+            user: &["username"],
+            password: &["password"],
+            notes: &["notes", "notesplain"],
+            otp: &["otpauth", "one-time password"],
+            tags: &["tags"],
+        },
+        Format::Keepassxc => Columns {
+            name: &["title"],
+            url: &["url"],
+            user: &["username"],
+            password: &["password"],
+            notes: &["notes"],
+            otp: &["totp"],
+            tags: &["group"],
+        },
+        Format::Chrome => Columns {
+            name: &["name"],
+            url: &["url"],
+            user: &["username"],
+            password: &["password"],
+            notes: &["note"],
+            otp: &[],
+            tags: &[],
+        },
+        Format::Firefox => Columns {
+            name: &[],
+            url: &["url"],
+            user: &["username"],
+            password: &["password"],
+            notes: &[],
+            otp: &[],
+            tags: &[],
+        },
+        Format::Onepass => Columns {
+            name: &["name"],
+            url: &["url"],
+            user: &["username"],
+            password: &["password"],
+            notes: &["notes"],
+            otp: &["totp"],
+            tags: &["tags"],
+        },
+    };
+    Some(columns)
+}
+
+/// Whether the data starts with the header row of `export::csv`.
+fn is_onepass_csv(data: &str) -> bool {
+    match data.trim_start().lines().next() {
+        Some(v) => v.trim_end() == export::CSV_COLUMNS.join(","),
+        None => false,
+    }
+}
+
+fn parse_csv(format: Format, data: &str) -> Result<Vec<Instance>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(data.as_bytes());
+    let headers: HashMap<String, usize> = match reader.headers() {
+        Ok(v) => v
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (h.trim().to_lowercase(), i))
+            .collect(),
+        Err(err) => return Err(err.to_string()),
+    };
+    let columns = match columns(format) {
+        Some(v) => v,
+        None => return Err(format!("{} is not a CSV format", format_name(format))),
+    };
+    if !columns.password.iter().any(|v| headers.contains_key(*v)) {
+        return Err(format!(
+            "No `{}` column - is this a {} export?",
+            columns.password[0],
+            format_name(format)
+        ));
+    }
+
+    let mut resources = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(v) => v,
+            Err(err) => return Err(format!("row {}: {}", i + 2, err)),
+        };
+        let get = |names: &[&str]| -> String {
+            names
+                .iter()
+                .filter_map(|n| headers.get(*n))
+                .filter_map(|i| record.get(*i))
+                .find(|v| !v.trim().is_empty())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        // Passwords and notes are kept as they are, spaces included.
+        let mut url = get(columns.url).trim().to_string();
+        // LastPass marks secure notes with this URL.
+        if format == Format::Lastpass && url == "http://sn" {
+            url.clear();
+        }
+        let tags = match format {
+            Format::Keepassxc | Format::Lastpass | Format::BitwardenCsv => {
+                group_tags(&get(columns.tags))
+            }
+            _ => resource::parse_tags(&get(columns.tags)),
+        };
+        let fields = match format {
+            Format::BitwardenCsv => bitwarden_fields(&get(&["fields"])),
+            Format::Onepass => onepass_fields(&get(&["fields"])),
+            _ => BTreeMap::new(),
+        };
+        let user = get(columns.user).trim().to_string();
+        resources.push(Instance {
+            name: name_for(get(columns.name).trim(), &url, &user),
+            user,
+            password: Secret::new(get(columns.password)),
+            url,
+            notes: get(columns.notes),
+            tags,
+            otp: Secret::from(get(columns.otp).trim()),
+            fields,
+            changed: 0,
+            generator: None,
+            history: vec![],
+        });
+    }
+    Ok(resources)
+}
+
+fn format_name(format: Format) -> String {
+    match format.to_possible_value() {
+        Some(v) => v.get_name().to_string(),
+        None => format!("{:?}", format),
+    }
+}
+
+/// Tags from a folder path like `Root/Email/Work`, without the `Root`
+/// KeePassXC puts everything in.
+fn group_tags(group: &str) -> Vec<String> {
+    let path = group
+        .split(['/', '\\'])
+        .filter(|v| *v != "Root")
+        .collect::<Vec<_>>()
+        .join(",");
+    resource::parse_tags(&path)
+}
+
+/// Bitwarden puts custom fields in one column, a `name: value` pair per line.
+fn bitwarden_fields(column: &str) -> BTreeMap<String, Secret> {
+    column
+        .lines()
+        .filter_map(|v| v.split_once(": "))
+        .filter(|(k, _)| !k.trim().is_empty())
+        .map(|(k, v)| (k.trim().to_string(), Secret::from(v)))
+        .collect()
+}
+
+/// The name of the entry, or else the host of its URL, or else its username.
+/// `name: value` lines, as `export::csv` writes the custom fields.
+fn onepass_fields(column: &str) -> BTreeMap<String, Secret> {
+    column
+        .lines()
+        .filter_map(|v| v.split_once(": "))
+        .map(|(k, v)| (k.to_string(), Secret::from(v)))
+        .collect()
+}
+
+fn name_for(name: &str, url: &str, user: &str) -> String {
+    if !name.is_empty() {
+        return name.to_string();
+    }
+    let host = url.split("://").last().unwrap_or_default();
+    let host = host.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    if !host.is_empty() {
+        return host.to_string();
+    }
+    if !user.is_empty() {
+        return user.to_string();
+    }
+    "imported".to_string()
+}
+
+#[derive(Deserialize)]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    name: Option<String>,
+    notes: Option<String>,
+    folder_id: Option<String>,
+    login: Option<BitwardenLogin>,
+    #[serde(default)]
+    fields: Vec<BitwardenField>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenLogin {
+    username: Option<String>,
+    password: Option<Secret>,
+    totp: Option<Secret>,
+    #[serde(default)]
+    uris: Vec<BitwardenUri>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenField {
+    name: Option<String>,
+    value: Option<Secret>,
+}
+
+fn parse_bitwarden_json(data: &str) -> Result<Vec<Instance>, String> {
+    let export: BitwardenExport = match serde_json::from_str(data) {
+        Ok(v) => v,
+        Err(err) => return Err(format!("Not a Bitwarden JSON export: {}", err)),
+    };
+    if export.encrypted {
+        return Err(
+            "Encrypted Bitwarden exports can not be imported, export as plain JSON".to_string(),
+        );
+    }
+    let folders: HashMap<String, String> =
+        export.folders.into_iter().map(|v| (v.id, v.name)).collect();
+
+    let mut resources = vec![];
+    for item in export.items {
+        let (user, password, otp, url) = match item.login {
+            Some(v) => (
+                v.username.unwrap_or_default(),
+                v.password.unwrap_or_default(),
+                v.totp.unwrap_or_default(),
+                v.uris.into_iter().find_map(|u| u.uri).unwrap_or_default(),
+            ),
+            None => Default::default(),
+        };
+        let tags = match item.folder_id.and_then(|id| folders.get(&id)) {
+            Some(v) => group_tags(v),
+            None => vec![],
+        };
+        let fields = item
+            .fields
+            .into_iter()
+            .filter_map(|f| Some((f.name?, f.value.unwrap_or_default())))
+            .filter(|(k, _)| !k.is_empty())
+            .collect();
+        resources.push(Instance {
+            name: name_for(item.name.as_deref().unwrap_or_default().trim(), &url, &user),
+            user,
+            password,
+            url,
+            notes: item.notes.unwrap_or_default(),
+            tags,
+            otp,
+            fields,
+            changed: 0,
+            generator: None,
+            history: vec![],
+        });
+    }
+    Ok(resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(resources: &[Instance]) -> Vec<(String, String, String, String)> {
+        resources
+            .iter()
+            .map(|v| {
+                (
+                    v.name.clone(),
+                    v.user.clone(),
+                    v.password.expose().to_string(),
+                    v.url.clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn entry(
+        name: &str,
+        user: &str,
+        password: &str,
+        url: &str,
+    ) -> (String, String, String, String) {
+        (
+            name.to_string(),
+            user.to_string(),
+            password.to_string(),
+            url.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_parse_bitwarden_csv() {
+        let data = "folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp
+Work,,login,GitHub,\"two\nlines\",\"api: k1\nid: 7\",0,https://github.com,octocat,pw1,JBSWY3DPEHPK3PXP
+,,note,Wifi,the code is 1234,,,,,,
+";
+        let got = parse(Format::BitwardenCsv, data).expect("parsing");
+        assert_eq!(
+            vec![
+                entry("GitHub", "octocat", "pw1", "https://github.com"),
+                entry("Wifi", "", "", ""),
+            ],
+            summary(&got)
+        );
+        assert_eq!("two\nlines", got[0].notes);
+        assert_eq!(vec!["Work"], got[0].tags);
+        assert_eq!("JBSWY3DPEHPK3PXP", got[0].otp.expose());
+        assert_eq!("k1", got[0].fields["api"].expose());
+        assert_eq!("7", got[0].fields["id"].expose());
+        assert_eq!("the code is 1234", got[1].notes);
+    }
+
+    #[test]
+    fn test_parse_bitwarden_json() {
+        let data = r#"{
+            "encrypted": false,
+            "folders": [{"id": "f1", "name": "Work"}],
+            "items": [
+                {
+                    "type": 1, "name": "GitHub", "notes": null, "folderId": "f1",
+                    "login": {
+                        "username": "octocat", "password": "pw1", "totp": null,
+                        "uris": [{"match": null, "uri": "https://github.com"}]
+                    },
+                    "fields": [{"name": "api", "value": "k1", "type": 0}]
+                },
+                {"type": 2, "name": "Wifi", "notes": "1234", "folderId": null}
+            ]
+        }"#;
+        let got = parse(Format::BitwardenJson, data).expect("parsing");
+        assert_eq!(
+            vec![
+                entry("GitHub", "octocat", "pw1", "https://github.com"),
+                entry("Wifi", "", "", ""),
+            ],
+            summary(&got)
+        );
+        assert_eq!(vec!["Work"], got[0].tags);
+        assert_eq!("k1", got[0].fields["api"].expose());
+        assert_eq!("1234", got[1].notes);
+
+        let err = parse(Format::BitwardenJson, r#"{"encrypted": true}"#).unwrap_err();
+        assert!(err.contains("Encrypted"));
+        assert!(parse(Format::BitwardenJson, "name,password").is_err());
+    }
+
+    #[test]
+    fn test_parse_lastpass() {
+        let data = "url,username,password,totp,extra,name,grouping,fav
+https://mail.example.com,me,pw1,,,Mail,Personal\\Email,0
+http://sn,,,,secret note,Note,,0
+";
+        let got = parse(Format::Lastpass, data).expect("parsing");
+        assert_eq!(
+            vec![
+                entry("Mail", "me", "pw1", "https://mail.example.com"),
+                entry("Note", "", "", ""),
+            ],
+            summary(&got)
+        );
+        assert_eq!(vec!["Personal", "Email"], got[0].tags);
+        assert_eq!("secret note", got[1].notes);
+    }
+
+    #[test]
+    fn test_parse_1password() {
+        let data = "Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes
+Bank,https://bank.example,me,pw1,otpauth://totp/x?secret=GEZDGNBVGY3TQOJQ,false,false,\"money,home\",call first
+";
+        let got = parse(Format::OnePassword, data).expect("parsing");
+        assert_eq!(
+            vec![entry("Bank", "me", "pw1", "https://bank.example")],
+            summary(&got)
+        );
+        assert_eq!(vec!["money", "home"], got[0].tags);
+        assert!(got[0].otp.expose().starts_with("otpauth://"));
+        assert_eq!("call first", got[0].notes);
+    }
+
+    #[test]
+    fn test_parse_keepassxc() {
+        let data = "\"Group\",\"Title\",\"Username\",\"Password\",\"URL\",\"Notes\",\"TOTP\",\"Icon\",\"Last Modified\",\"Created\"
+\"Root/Email\",\"Mail\",\"me\",\"pw1\",\"https://mail.example.com\",\"\",\"\",\"0\",\"2024-01-01T00:00:00Z\",\"2024-01-01T00:00:00Z\"
+\"Root\",\"Router\",\"admin\",\"pw2\",\"\",\"\",\"\",\"0\",\"\",\"\"
+";
+        let got = parse(Format::Keepassxc, data).expect("parsing");
+        assert_eq!(
+            vec![
+                entry("Mail", "me", "pw1", "https://mail.example.com"),
+                entry("Router", "admin", "pw2", ""),
+            ],
+            summary(&got)
+        );
+        assert_eq!(vec!["Email"], got[0].tags);
+        assert!(got[1].tags.is_empty());
+    }
+
+    #[test]
+    fn test_parse_browsers() {
+        let data = "name,url,username,password,note
+github.com,https://github.com/login,octocat,pw1,
+";
+        let got = parse(Format::Chrome, data).expect("parsing");
+        assert_eq!(
+            vec![entry(
+                "github.com",
+                "octocat",
+                "pw1",
+                "https://github.com/login"
+            )],
+            summary(&got)
+        );
+
+        let data = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"
+\"https://user@accounts.example.com:8443/login\",\"me\",\"pw1\",,\"https://accounts.example.com\",\"{1}\",\"1\",\"1\",\"1\"
+";
+        let got = parse(Format::Firefox, data).expect("parsing");
+        assert_eq!(
+            vec![entry(
+                "accounts.example.com",
+                "me",
+                "pw1",
+                "https://user@accounts.example.com:8443/login"
+            )],
+            summary(&got)
+        );
+
+        let err = parse(Format::Chrome, "a,b\n1,2\n").unwrap_err();
+        assert_eq!("No `password` column - is this a chrome export?", err);
+    }
+
+    #[test]
+    fn test_merge() {
+        let resource = |name: &str, password: &str| Instance {
+            name: name.to_string(),
+            password: Secret::from(password),
+            ..Default::default()
+        };
+        let existing = || vec![resource("github", "old"), resource("github (2)", "old")];
+        let imported = || vec![resource("github", "new"), resource("mail", "new")];
+
+        let (got, report) = merge(existing(), imported(), Duplicates::Skip);
+        assert_eq!(3, got.len());
+        assert_eq!("old", got[0].password.expose());
+        assert_eq!(vec!["mail"], report.added);
+        assert_eq!(vec!["github"], report.skipped);
+
+        let (got, report) = merge(existing(), imported(), Duplicates::Replace);
+        assert_eq!(3, got.len());
+        assert_eq!("new", got[0].password.expose());
+        assert_eq!(vec!["github"], report.replaced);
+
+        let (got, report) = merge(existing(), imported(), Duplicates::Rename);
+        assert_eq!(4, got.len());
+        assert_eq!("github (3)", got[2].name);
+        assert_eq!(
+            vec![("github".to_string(), "github (3)".to_string())],
+            report.renamed
+        );
+        assert_eq!(vec!["mail"], report.added);
+    }
+}
@@ -1,30 +1,77 @@
+use crate::breach;
+use crate::otp;
 use crate::password;
 use crate::resource;
-use crate::text;
+use crate::secret::Secret;
 
+use std::collections::BTreeMap;
 use std::io::Stdin;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 pub static MODE: AtomicBool = AtomicBool::new(false);
 
-pub fn master_password() -> Result<String, String> {
+pub fn master_password() -> Result<Secret, String> {
+    prompt_master_password("master password: ")
+}
+
+/// Ask for a new master password twice and make sure both match.
+pub fn new_master_password() -> Result<Secret, String> {
+    let first = prompt_master_password("new master password: ")?;
+    let second = prompt_master_password("repeat new master password: ")?;
+    if first != second {
+        return Err("passwords do not match".to_string());
+    }
+    Ok(first)
+}
+
+/// Ask for the passphrase of a portable archive, twice when creating one.
+/// Unlike master passwords, passphrases may contain spaces.
+pub fn archive_passphrase(new: bool) -> Result<Secret, String> {
+    let first = prompt_passphrase("archive passphrase: ")?;
+    if new && first != prompt_passphrase("repeat archive passphrase: ")? {
+        return Err("passphrases do not match".to_string());
+    }
+    Ok(first)
+}
+
+fn prompt_passphrase(prompt: &str) -> Result<Secret, String> {
+    MODE.store(true, Ordering::Relaxed);
+    let input = match rpassword::prompt_password(prompt) {
+        Ok(v) => Secret::new(v),
+        Err(err) => return Err(err.to_string()),
+    };
+    MODE.store(false, Ordering::Relaxed);
+
+    if input.expose().trim().is_empty() {
+        return Err("passphrase can not be empty".to_string());
+    }
+    Ok(input)
+}
+
+fn prompt_master_password(prompt: &str) -> Result<Secret, String> {
     MODE.store(true, Ordering::Relaxed);
-    let input = match rpassword::prompt_password("master password: ") {
-        Ok(v) => v,
+    let input = match rpassword::prompt_password(prompt) {
+        Ok(v) => Secret::new(v),
         Err(err) => return Err(err.to_string()),
     };
     MODE.store(false, Ordering::Relaxed);
 
-    if input.trim().is_empty() {
+    if input.expose().trim().is_empty() {
         return Err("password can not be empty".to_string());
     };
-    if input.contains(' ') {
+    if input.expose().contains(' ') {
         return Err("password can not contain spaces".to_string());
     };
     Ok(input)
 }
 
-pub fn resource(i: &mut Stdin) -> Result<resource::Instance, String> {
+/// Ask for a new resource, offering a password of `generator`. Typed passwords
+/// are checked against the local breach copy at `breach_db`, if any.
+pub fn resource(
+    i: &mut Stdin,
+    generator: &password::Generator,
+    breach_db: Option<&str>,
+) -> Result<resource::Instance, String> {
     MODE.store(true, Ordering::Relaxed);
     let fn_ask_for = |m: &str| -> Result<String, String> {
         println!("{}: ", m);
@@ -32,35 +79,103 @@ pub fn resource(i: &mut Stdin) -> Result<resource::Instance, String> {
         if let Err(err) = i.read_line(&mut input) {
             return Err(err.to_string());
         }
-        if is_reserved(&input) {
-            return Err("use of reserved keyword".to_string());
-        };
         Ok(input.trim().to_string())
     };
     let name = fn_ask_for("resource")?;
     let user = fn_ask_for("user")?;
-    let yes_no = fn_ask_for("generated a strong password, do you want to use it? (y/n)")?;
-    let password: String = if yes_no == "y" {
-        password::suggest(14)
+    let generated = Secret::new(generator.generate()?);
+    let yes_no = fn_ask_for(&format!(
+        "generated a strong password (about {:.0} bits), do you want to use it? (y/n)",
+        generator.entropy()
+    ))?;
+    let accepted = yes_no == "y";
+    let password = if accepted {
+        generated
     } else {
-        match rpassword::prompt_password("choose a password: ") {
-            Ok(v) => v,
+        let password = match rpassword::prompt_password("choose a password: ") {
+            Ok(v) => Secret::new(v),
             Err(err) => return Err(err.to_string()),
+        };
+        if let Some(path) = breach_db {
+            warn_breached(path, &password);
         }
+        password
+    };
+    let url = fn_ask_for("url (optional)")?;
+    let notes = fn_ask_for("notes (optional)")?;
+    let tags = resource::parse_tags(&fn_ask_for("tags, comma separated (optional)")?);
+    let otp = match rpassword::prompt_password("totp secret or otpauth:// uri (optional): ") {
+        Ok(v) => Secret::new(v),
+        Err(err) => return Err(err.to_string()),
     };
+    let otp = Secret::from(otp.expose().trim());
+    if !otp.is_empty() {
+        otp::parse(otp.expose())?;
+    }
+    let mut fields = BTreeMap::new();
+    loop {
+        let field = fn_ask_for("custom field name (optional, empty to finish)")?;
+        if field.is_empty() {
+            break;
+        }
+        // Values like security answers are secrets, so they are not echoed.
+        let val = match rpassword::prompt_password(format!("{}: ", field)) {
+            Ok(v) => Secret::new(v),
+            Err(err) => return Err(err.to_string()),
+        };
+        fields.insert(field, Secret::from(val.expose().trim()));
+    }
     MODE.store(false, Ordering::Relaxed);
     Ok(resource::Instance {
         name,
         user,
         password,
+        url,
+        notes,
+        tags,
+        otp,
+        fields,
+        changed: 0,
+        // Only options given for this resource, and only if the password
+        // made with them was taken, are worth remembering.
+        generator: (accepted && *generator != password::Generator::default())
+            .then(|| generator.clone()),
+        history: vec![],
     })
 }
 
+/// Ask a yes or no question, anything but `y` is a no.
+pub fn confirm(i: &mut Stdin, question: &str) -> Result<bool, String> {
+    println!("{} (y/n)", question);
+    let mut input = String::new();
+    if let Err(err) = i.read_line(&mut input) {
+        return Err(err.to_string());
+    }
+    Ok(input.trim() == "y")
+}
+
+/// Only warns, the password is used either way.
+fn warn_breached(path: &str, password: &Secret) {
+    match breach::Db::open(path).and_then(|db| db.count(password.expose())) {
+        Ok(0) => {}
+        Ok(n) => println!(
+            "warning: this password appears {} times in known breaches, consider another one",
+            n
+        ),
+        Err(err) => println!(
+            "warning: could not check the password for breaches: {}",
+            err
+        ),
+    }
+}
+
 // Returns a tuple of (Key, Value) of a resource to update.
 // E.g (resource::Key::NAME, new_name).
-pub fn update_resource(i: &mut Stdin) -> Result<(resource::Key, String), String> {
+pub fn update_resource(i: &mut Stdin) -> Result<(resource::Key, Secret), String> {
     MODE.store(true, Ordering::Relaxed);
-    println!("update name (n), user (u) or password (p)?");
+    println!(
+        "update name (n), user (u), password (p), url (l), notes (o), tags (t), totp secret (s) or a custom field (f)?"
+    );
     let mut target = String::new();
     if let Err(err) = i.read_line(&mut target) {
         return Err(err.to_string());
@@ -69,6 +184,22 @@ pub fn update_resource(i: &mut Stdin) -> Result<(resource::Key, String), String>
         "n\n" => resource::Key::Name,
         "u\n" => resource::Key::User,
         "p\n" => resource::Key::Password,
+        "l\n" => resource::Key::Url,
+        "o\n" => resource::Key::Notes,
+        "t\n" => resource::Key::Tags,
+        "s\n" => resource::Key::Otp,
+        "f\n" => {
+            println!("custom field name: ");
+            let mut field = String::new();
+            if let Err(err) = i.read_line(&mut field) {
+                return Err(err.to_string());
+            }
+            let field = field.trim().to_string();
+            if field.is_empty() {
+                return Err("field name can not be empty".to_string());
+            }
+            resource::Key::Field(field)
+        }
         _ => return Err("Unsupported command".to_string()),
     };
 
@@ -92,22 +223,43 @@ pub fn update_resource(i: &mut Stdin) -> Result<(resource::Key, String), String>
                 Err(err) => return Err(err.to_string()),
             };
         }
+        resource::Key::Url => {
+            println!("new url: ");
+            if let Err(err) = i.read_line(&mut val) {
+                return Err(err.to_string());
+            }
+        }
+        resource::Key::Notes => {
+            println!("new notes: ");
+            if let Err(err) = i.read_line(&mut val) {
+                return Err(err.to_string());
+            }
+        }
+        resource::Key::Tags => {
+            println!("new tags, comma separated: ");
+            if let Err(err) = i.read_line(&mut val) {
+                return Err(err.to_string());
+            }
+        }
+        resource::Key::Otp => {
+            val = match rpassword::prompt_password("new totp secret or otpauth:// uri: ") {
+                Ok(v) => v,
+                Err(err) => return Err(err.to_string()),
+            };
+            if !val.trim().is_empty() {
+                otp::parse(&val)?;
+            }
+        }
+        resource::Key::Field(ref field) => {
+            let prompt = format!("new value of {} (empty to remove it): ", field);
+            val = match rpassword::prompt_password(prompt) {
+                Ok(v) => v,
+                Err(err) => return Err(err.to_string()),
+            };
+        }
     }
-    val = val.trim().to_string();
+    let val = Secret::new(val);
 
     MODE.store(false, Ordering::Relaxed);
-    Ok((key, val))
-}
-
-pub fn drop_clipboard_ctx(i: &mut Stdin) {
-    MODE.store(true, Ordering::Relaxed);
-    println!("Press any button to drop clipboard context");
-    let mut target = String::new();
-    if let Err(err) = i.read_line(&mut target) {
-        println!("{err}");
-    }
-}
-
-pub fn is_reserved(input: &str) -> bool {
-    input == text::RESERVED_NONCE || input == text::RESERVED_RESOURCE
+    Ok((key, Secret::from(val.expose().trim())))
 }
@@ -0,0 +1,180 @@
+use std::fmt;
+
+use crate::harden::Locked;
+use crate::secret::Secret;
+use argon2::{Algorithm, Argon2, Version};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+pub const SALT_LEN: usize = 16;
+pub const KEY_LEN: usize = 32;
+
+/// Argon2id cost parameters, stored alongside the salt in the file header
+/// so they can be raised later without breaking existing vaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// Memory cost in KiB.
+    pub m_cost: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+}
+
+impl Params {
+    /// Upper bounds for parameters read from a file or the agent, so a
+    /// damaged or forged header can not make deriving a key take more than
+    /// 1 GiB of memory and some seconds before the file fails to decrypt.
+    /// Far above the defaults, which leaves room to raise them.
+    pub const MAX: Params = Params {
+        m_cost: 1024 * 1024,
+        t_cost: 10,
+        p_cost: 16,
+    };
+
+    /// Reject parameters beyond `MAX`.
+    pub fn check(&self) -> Result<(), String> {
+        if self.m_cost > Params::MAX.m_cost
+            || self.t_cost > Params::MAX.t_cost
+            || self.p_cost > Params::MAX.p_cost
+        {
+            return Err(format!(
+                "key derivation parameters out of range: {} KiB, {} passes, {} lanes",
+                self.m_cost, self.t_cost, self.p_cost
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What unlocks a vault: the master password, or a key derived from it
+/// earlier along with the salt and parameters it was derived with.
+#[derive(Debug)]
+pub enum Credential {
+    Password(Secret),
+    Key(DerivedKey),
+}
+
+pub struct DerivedKey {
+    pub salt: Vec<u8>,
+    pub params: Params,
+    pub key: Key,
+}
+
+impl fmt::Debug for DerivedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DerivedKey")
+            .field("salt", &self.salt)
+            .field("params", &self.params)
+            .field("key", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// A cipher key, kept out of swap and wiped from memory when dropped.
+pub type Key = Locked<KEY_LEN>;
+
+pub fn salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// Derive the vault key from the master password with Argon2id.
+pub fn derive(password: &str, salt: &[u8], params: Params) -> Result<Key, String> {
+    let argon_params =
+        match argon2::Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_LEN)) {
+            Ok(v) => v,
+            Err(err) => return Err(err.to_string()),
+        };
+    let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params);
+
+    let mut key = Key::zeroed();
+    if let Err(err) = argon.hash_password_into(password.as_bytes(), salt, &mut key[..]) {
+        return Err(err.to_string());
+    }
+    Ok(key)
+}
+
+/// Derive the key used by vaults written before Argon2id was introduced.
+pub fn derive_legacy(password: &str) -> Key {
+    let mut key = Key::zeroed();
+    key.copy_from_slice(&Zeroizing::new(hmac_sha256::Hash::hash(password.as_bytes()))[..]);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PARAMS: Params = Params {
+        m_cost: 1024,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn test_derive_deterministic() {
+        let salt = salt();
+        let a = derive("master", &salt, TEST_PARAMS).expect("deriving");
+        let b = derive("master", &salt, TEST_PARAMS).expect("deriving");
+        assert_eq!(*a, *b);
+    }
+
+    #[test]
+    fn test_derive_depends_on_salt_and_password() {
+        let salt_a = salt();
+        let salt_b = salt();
+        assert_ne!(salt_a, salt_b);
+
+        let a = derive("master", &salt_a, TEST_PARAMS).expect("deriving");
+        let b = derive("master", &salt_b, TEST_PARAMS).expect("deriving");
+        let c = derive("other", &salt_a, TEST_PARAMS).expect("deriving");
+        assert_ne!(*a, *b);
+        assert_ne!(*a, *c);
+        assert_ne!(*a, *derive_legacy("master"));
+    }
+
+    #[test]
+    fn test_check_params() {
+        Params::default().check().expect("checking");
+        Params::MAX.check().expect("checking");
+        for params in [
+            Params {
+                m_cost: Params::MAX.m_cost + 1,
+                ..Params::default()
+            },
+            Params {
+                t_cost: Params::MAX.t_cost + 1,
+                ..Params::default()
+            },
+            Params {
+                p_cost: Params::MAX.p_cost + 1,
+                ..Params::default()
+            },
+        ] {
+            assert!(params.check().is_err());
+        }
+    }
+
+    #[test]
+    fn test_derive_rejects_bad_params() {
+        let params = Params {
+            m_cost: 1,
+            t_cost: 0,
+            p_cost: 0,
+        };
+        assert!(derive("master", &salt(), params).is_err());
+    }
+}
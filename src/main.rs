@@ -1,18 +1,50 @@
+mod agent;
+mod audit;
+mod backup;
+mod breach;
+mod cli;
+mod clipboard;
 mod command;
+mod config;
+mod export;
 mod file;
+mod harden;
+mod header;
+mod import;
 mod input;
+mod kdf;
+mod otp;
 mod password;
+mod picker;
 mod resource;
+mod search;
+mod secret;
+mod shell;
+mod strength;
 mod text;
+mod tui;
 
-use command::Kind;
-use std::env;
+use std::process::ExitCode;
 use std::sync::atomic::Ordering;
 
-// extern crate arboard;
+use clap::CommandFactory;
+use clap_complete::CompleteEnv;
+
+use cli::{AgentAction, BackupAction, Cli, Command};
+
 extern crate rpassword;
 
-fn main() {
+fn main() -> ExitCode {
+    // Answers the scripts from `onepass completions --dynamic`, exits if it did.
+    CompleteEnv::with_factory(Cli::command)
+        .var(cli::COMPLETE_VAR)
+        .complete();
+
+    let cli = cli::parse();
+    if !cli.no_harden {
+        harden::apply();
+    }
+
     ctrlc::set_handler(move || {
         println!("onepass: cleaning up...");
         if input::MODE.load(Ordering::Relaxed) {
@@ -25,76 +57,85 @@ fn main() {
             }
             std::thread::sleep(std::time::Duration::from_secs(1));
         }
-        println!("onepass: WARNING: bad state, run `onepass purge`")
+        // Writes are atomic, so the vault holds either its old or its new content.
+        println!("onepass: interrupted before the command finished");
+        std::process::exit(1);
     })
     .expect("setting ctrl-c handler");
 
-    let mut stdin = std::io::stdin();
-
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("{}", text::MSG_HELP);
-        std::process::exit(0);
-    }
-    let command_string = &args[1];
-
-    let mut path: Option<String> = None;
-    for i in 2..args.len() {
-        if args[i] == "-l" || args[i] == "--location" {
-            match args.get(i + 1) {
-                None => {
-                    println!("{}", text::MSG_HELP);
-                    std::process::exit(0);
-                }
-                Some(v) => path = Some(v.to_string()),
-            }
+    match run(cli) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
         }
     }
+}
 
-    let cmd = match command::Kind::from_string(command_string.as_str()) {
-        Some(v) => v,
-        None => {
-            println!("{}", text::MSG_HELP);
-            std::process::exit(1);
-        }
-    };
-    match cmd {
-        Kind::New => {
-            if let Err(err) = command::new(path.as_deref(), &mut stdin) {
-                println!("{}", &err);
-            };
-        }
-        Kind::Get => {
-            match command::get(path.as_deref(), args) {
-                Ok(_) => input::drop_clipboard_ctx(&mut stdin),
-                Err(e) => println!("{}", &e),
-            };
-        }
-        Kind::Del => {
-            if let Err(err) = command::del(path.as_deref(), args) {
-                println!("{}", &err);
-            };
-        }
-        Kind::Suggest => {
-            println!("{}", command::suggest());
-        }
-        Kind::List => {
-            if let Err(err) = command::list(path.as_deref()) {
-                println!("{}", &err);
-            };
-        }
-        Kind::Purge => {
-            if let Err(err) = command::purge() {
-                println!("{}", &err);
-            };
-        }
-        Kind::Update => {
-            if let Err(err) = command::update(path.as_deref(), args, &mut stdin) {
-                println!("{}", &err);
-            };
+fn run(cli: Cli) -> Result<(), String> {
+    let mut stdin = std::io::stdin();
+    let path = cli.location.as_deref();
+
+    match cli.command {
+        Command::New { generator } => command::new(path, &generator.generator(), &mut stdin),
+        Command::Get {
+            name,
+            clear_after,
+            history,
+        } => command::get(path, &name, clear_after, history),
+        Command::Otp { name, clear_after } => command::otp(path, &name, clear_after),
+        Command::Find { query } => command::find(path, &query),
+        Command::Rotate {
+            name,
+            clear_after,
+            generator,
+        } => command::rotate(path, &name, generator.custom(), clear_after, &mut stdin),
+        Command::Del { name } => command::del(path, &name),
+        Command::Tui { lock_after } => command::tui(path, lock_after),
+        Command::Audit {
+            json,
+            fail_on,
+            max_age,
+        } => command::audit(path, json, fail_on, max_age),
+        Command::BreachCheck { db } => command::breach_check(path, db.as_deref()),
+        Command::Import {
+            format,
+            file,
+            duplicates,
+            dry_run,
+        } => command::import(path, format, &file, duplicates, dry_run),
+        Command::Export {
+            format,
+            output,
+            plaintext,
+        } => command::export(path, format, output.as_deref(), plaintext),
+        Command::Shell { lock_after } => command::shell(path, lock_after, &mut stdin),
+        Command::List { tag, url } => command::list(path, resource::Filter { tag, url }),
+        Command::Update { name } => command::update(path, &name, &mut stdin),
+        Command::Purge => command::purge(path),
+        Command::Backup { action } => match action {
+            BackupAction::List => command::backup_list(path),
+            BackupAction::Restore { id } => command::backup_restore(path, &id),
+        },
+        Command::Passwd => command::passwd(path),
+        Command::Agent {
+            action: Some(AgentAction::Stop),
+            ..
+        } => command::agent_stop(),
+        Command::Agent {
+            action: None,
+            timeout,
+            foreground,
+        } => command::agent(timeout, foreground),
+        Command::Lock => command::lock(),
+        Command::Doctor => {
+            command::doctor(path);
+            Ok(())
         }
-        Kind::Help => {
-            println!("{}", command::help(args));
+        Command::Suggest { generator } => command::suggest(&generator.generator()),
+        Command::Completions { shell, dynamic } => {
+            cli::write_completions(shell, dynamic, &mut std::io::stdout())
         }
+        Command::ClipboardHelper { timeout } => command::clipboard_helper(timeout),
     }
 }
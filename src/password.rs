@@ -1,42 +1,235 @@
+use std::sync::OnceLock;
+
+use rand::rngs::OsRng;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 const UPPERCASE: &[u8; 26] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
 const LOWERCASE: &[u8; 26] = b"abcdefghijklmnopqrstuvwxyz";
 const NUMBERS: &[u8; 10] = b"0123456789";
 const SPECIAL_CHARS: &[u8; 26] = b"!@#$%^&*()-_=+[]{}|;:,.<>?";
+/// Characters easily mistaken for one another when read or typed.
+const AMBIGUOUS: &[u8] = b"0O1lI|";
 
-pub fn suggest(length: usize) -> String {
-    let mut rng = rand::thread_rng();
+/// Words for passphrases, one per line as in the EFF large wordlist, which
+/// has one for each of the 7776 rolls of five dice. Only the last field of
+/// a line is read, so the dice numbers of the published file can stay.
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+pub const DEFAULT_LENGTH: usize = 16;
+pub const DEFAULT_WORDS: usize = 6;
+pub const DEFAULT_SEPARATOR: &str = "-";
+
+/// How a new password is made. Resources keep theirs for `onepass rotate`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Generator {
+    Password(Policy),
+    Passphrase(Passphrase),
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator::Password(Policy::default())
+    }
+}
+
+impl Generator {
+    pub fn generate(&self) -> Result<String, String> {
+        match self {
+            Generator::Password(v) => generate(v),
+            Generator::Passphrase(v) => passphrase(v),
+        }
+    }
+
+    /// Bits of entropy of what `generate` makes.
+    pub fn entropy(&self) -> f64 {
+        match self {
+            Generator::Password(v) => v.entropy(),
+            Generator::Passphrase(v) => v.entropy(),
+        }
+    }
+}
+
+/// What a generated password is made of.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    pub length: usize,
+    /// Symbols to pick from, none if empty.
+    pub symbols: String,
+    pub exclude_ambiguous: bool,
+    pub min_digits: usize,
+    /// Characters never to use, e.g those a site rejects.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub exclude: String,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            length: DEFAULT_LENGTH,
+            symbols: String::from_utf8_lossy(SPECIAL_CHARS).to_string(),
+            exclude_ambiguous: false,
+            min_digits: 1,
+            exclude: String::new(),
+        }
+    }
+}
+
+impl Policy {
+    /// The character sets to pick from: uppercase, lowercase, digits and
+    /// symbols, if there are any.
+    fn sets(&self) -> Result<Vec<Vec<u8>>, String> {
+        let mut symbols = self.symbols.as_bytes().to_vec();
+        if symbols
+            .iter()
+            .any(|c| !c.is_ascii_graphic() || c.is_ascii_alphanumeric())
+        {
+            return Err("symbols must be printable ASCII, not letters or digits".to_string());
+        }
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let mut sets = vec![UPPERCASE.to_vec(), LOWERCASE.to_vec(), NUMBERS.to_vec()];
+        if !symbols.is_empty() {
+            sets.push(symbols);
+        }
+        for set in sets.iter_mut() {
+            set.retain(|c| {
+                let ambiguous = self.exclude_ambiguous && AMBIGUOUS.contains(c);
+                !ambiguous && !self.exclude.as_bytes().contains(c)
+            });
+        }
+        if sets[2].is_empty() && self.min_digits > 0 {
+            return Err("no digits are left to use".to_string());
+        }
+        sets.retain(|v| !v.is_empty());
+        if sets.is_empty() {
+            return Err("no characters are left to use".to_string());
+        }
+
+        // One of each set but the digits, which have their own minimum.
+        let has_digits = sets.iter().any(|v| v[0].is_ascii_digit());
+        let required = sets.len() - usize::from(has_digits) + self.min_digits;
+        if required > self.length {
+            return Err(format!(
+                "a password of {} characters can not hold the {} required ones",
+                self.length, required
+            ));
+        }
+        Ok(sets)
+    }
+
+    /// Bits of entropy of a password generated from the policy, slightly
+    /// overestimated by ignoring the required characters.
+    pub fn entropy(&self) -> f64 {
+        let pool: usize = self
+            .sets()
+            .unwrap_or_default()
+            .iter()
+            .map(|v| v.len())
+            .sum();
+        match pool {
+            0 => 0.0,
+            _ => self.length as f64 * (pool as f64).log2(),
+        }
+    }
+}
+
+/// What a generated passphrase is made of.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Passphrase {
+    pub words: usize,
+    pub separator: String,
+    /// Start every word with an uppercase letter.
+    pub capitalize: bool,
+    /// Append a digit to one of the words.
+    pub with_number: bool,
+}
+
+impl Default for Passphrase {
+    fn default() -> Self {
+        Passphrase {
+            words: DEFAULT_WORDS,
+            separator: DEFAULT_SEPARATOR.to_string(),
+            capitalize: false,
+            with_number: false,
+        }
+    }
+}
 
-    // Make sure we include at least one of each character type.
-    let mut password = vec![
-        UPPERCASE[rng.gen_range(0..UPPERCASE.len())] as char,
-        LOWERCASE[rng.gen_range(0..LOWERCASE.len())] as char,
-        NUMBERS[rng.gen_range(0..NUMBERS.len())] as char,
-        SPECIAL_CHARS[rng.gen_range(0..SPECIAL_CHARS.len())] as char,
-    ];
-
-    let all_chars: Vec<u8> = UPPERCASE
-        .iter()
-        .chain(LOWERCASE)
-        .chain(NUMBERS)
-        .chain(SPECIAL_CHARS)
-        .copied()
+impl Passphrase {
+    /// Bits of entropy, capitalizing adds none as every word gets it.
+    pub fn entropy(&self) -> f64 {
+        let mut bits = self.words as f64 * (wordlist().len() as f64).log2();
+        if self.with_number && self.words > 0 {
+            bits += (10.0 * self.words as f64).log2();
+        }
+        bits
+    }
+}
+
+/// The words of `WORDLIST`, split once.
+fn wordlist() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        WORDLIST
+            .lines()
+            .filter_map(|v| v.split_whitespace().last())
+            .collect()
+    })
+}
+
+pub fn passphrase(options: &Passphrase) -> Result<String, String> {
+    if options.words == 0 {
+        return Err("a passphrase needs at least one word".to_string());
+    }
+    let wordlist = wordlist();
+    let mut words: Vec<String> = (0..options.words)
+        .map(|_| wordlist[OsRng.gen_range(0..wordlist.len())].to_string())
         .collect();
+    if options.capitalize {
+        for word in words.iter_mut() {
+            word[..1].make_ascii_uppercase();
+        }
+    }
+    if options.with_number {
+        let i = OsRng.gen_range(0..words.len());
+        words[i].push(char::from(b'0' + OsRng.gen_range(0..10)));
+    }
+    Ok(words.join(&options.separator))
+}
 
-    // Fill the rest of the password.
-    for _ in 4..length {
-        let random_char = all_chars[rng.gen_range(0..all_chars.len())] as char;
-        password.push(random_char);
+pub fn generate(policy: &Policy) -> Result<String, String> {
+    let sets = policy.sets()?;
+    let mut rng = rand::thread_rng();
+    // `gen_range` rejects samples that would favour some characters.
+    let mut pick = |set: &[u8]| set[rng.gen_range(0..set.len())];
+
+    let mut password = vec![];
+    for set in &sets {
+        if set[0].is_ascii_digit() {
+            for _ in 0..policy.min_digits {
+                password.push(pick(set));
+            }
+        } else {
+            password.push(pick(set));
+        }
+    }
+    let all_chars: Vec<u8> = sets.concat();
+    while password.len() < policy.length {
+        password.push(pick(&all_chars));
     }
 
-    // Shuffle the password vector by swapping random elements.
-    for i in 0..password.len() {
-        let j = rng.gen_range(0..password.len());
+    // Fisher-Yates, so the required characters can be anywhere.
+    for i in (1..password.len()).rev() {
+        let j = rng.gen_range(0..=i);
         password.swap(i, j);
     }
 
-    password.into_iter().collect()
+    Ok(password.into_iter().map(|c| c as char).collect())
 }
 
 #[cfg(test)]
@@ -50,7 +243,7 @@ mod tests {
             let mut found_uppercase: bool = false;
             let mut found_lowercase: bool = false;
             let mut found_special_char: bool = false;
-            let example = suggest(14);
+            let example = generate(&Policy::default()).expect("generating");
             for v in example.as_bytes() {
                 if UPPERCASE.contains(v) {
                     found_uppercase = true;
@@ -65,8 +258,8 @@ mod tests {
                     found_special_char = true;
                 }
             }
-            if example.len() != 14 {
-                panic!("password not 14 chars")
+            if example.len() != DEFAULT_LENGTH {
+                panic!("password not {} chars", DEFAULT_LENGTH)
             }
 
             if !found_number || !found_special_char || !found_lowercase || !found_uppercase {
@@ -74,4 +267,151 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_generate_policy() {
+        let policy = Policy {
+            length: 8,
+            symbols: "!#".to_string(),
+            exclude_ambiguous: true,
+            min_digits: 3,
+            exclude: "#xyz".to_string(),
+        };
+        for _ in 0..50 {
+            let v = generate(&policy).expect("generating");
+            assert_eq!(8, v.len());
+            assert!(v.bytes().filter(|c| c.is_ascii_digit()).count() >= 3);
+            assert!(v.bytes().any(|c| c == b'!'));
+            assert!(!v
+                .bytes()
+                .any(|c| AMBIGUOUS.contains(&c) || b"#xyz".contains(&c)));
+            assert!(v
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || b"!#".contains(&c)));
+        }
+
+        let no_symbols = Policy {
+            symbols: String::new(),
+            ..Default::default()
+        };
+        let v = generate(&no_symbols).expect("generating");
+        assert!(v.bytes().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn test_generate_invalid() {
+        let short = Policy {
+            length: 4,
+            min_digits: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            "a password of 4 characters can not hold the 5 required ones",
+            generate(&short).unwrap_err()
+        );
+        let letters = Policy {
+            symbols: "!a".to_string(),
+            ..Default::default()
+        };
+        assert!(generate(&letters).is_err());
+        let no_digits = Policy {
+            exclude: "0123456789".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            "no digits are left to use",
+            generate(&no_digits).unwrap_err()
+        );
+        let only_digits = Policy {
+            symbols: String::new(),
+            exclude: String::from_utf8_lossy(UPPERCASE).to_string()
+                + &String::from_utf8_lossy(LOWERCASE),
+            ..Default::default()
+        };
+        let v = generate(&only_digits).expect("generating");
+        assert!(v.bytes().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn test_generator_serde() {
+        let generators = vec![
+            Generator::Password(Policy {
+                length: 20,
+                exclude: "<>".to_string(),
+                ..Default::default()
+            }),
+            Generator::Passphrase(Passphrase::default()),
+        ];
+        let json = serde_json::to_string(&generators).expect("serializing");
+        assert!(json.starts_with(r#"[{"kind":"password","length":20,"#));
+        let got: Vec<Generator> = serde_json::from_str(&json).expect("parsing");
+        assert_eq!(generators, got);
+
+        let got: Generator =
+            serde_json::from_str(r#"{"kind":"password","length":12}"#).expect("parsing");
+        assert_eq!(
+            Generator::Password(Policy {
+                length: 12,
+                ..Default::default()
+            }),
+            got
+        );
+    }
+
+    #[test]
+    fn test_wordlist() {
+        let wordlist = wordlist();
+        assert_eq!(7776, wordlist.len());
+        // Every word counts towards the entropy only if it is there once.
+        let unique: std::collections::HashSet<_> = wordlist.iter().collect();
+        assert_eq!(wordlist.len(), unique.len());
+        assert!(wordlist.iter().all(|v| (3..=9).contains(&v.len())
+            && v.bytes().all(|c| c.is_ascii_lowercase() || c == b'-')));
+    }
+
+    #[test]
+    fn test_passphrase() {
+        let wordlist = wordlist();
+        let options = Passphrase {
+            words: 5,
+            separator: " ".to_string(),
+            capitalize: true,
+            with_number: true,
+        };
+        for _ in 0..20 {
+            let v = passphrase(&options).expect("generating");
+            let words: Vec<&str> = v.split(' ').collect();
+            assert_eq!(5, words.len());
+            assert!(words
+                .iter()
+                .all(|v| v.starts_with(|c: char| c.is_ascii_uppercase())));
+            assert_eq!(1, v.chars().filter(|c| c.is_ascii_digit()).count());
+            for word in words {
+                let word = word.trim_end_matches(|c: char| c.is_ascii_digit());
+                assert!(wordlist.contains(&word.to_lowercase().as_str()));
+            }
+        }
+
+        let bits = Passphrase::default().entropy();
+        assert!((bits - 6.0 * (wordlist.len() as f64).log2()).abs() < 1e-9);
+        assert!(passphrase(&Passphrase {
+            words: 0,
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_entropy() {
+        // 26 + 26 + 10 + 26 characters.
+        let bits = Policy::default().entropy();
+        assert!((bits - 16.0 * 88f64.log2()).abs() < 1e-9);
+
+        let no_symbols = Policy {
+            length: 10,
+            symbols: String::new(),
+            ..Default::default()
+        };
+        assert!((no_symbols.entropy() - 10.0 * 62f64.log2()).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,200 @@
+use std::io::{IsTerminal, Write};
+
+use crossterm::cursor::{MoveToColumn, MoveUp};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{queue, QueueableCommand};
+
+/// Most items shown at once, the list scrolls with the selection.
+const MAX_ITEMS: usize = 10;
+
+const PROMPT: &str = "Pick a resource: ";
+
+/// Whether there is a terminal to pick on. Without one, e.g in scripts,
+/// callers should fail instead of waiting for keys that never come.
+pub fn available() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Let the user pick one of the items `rank` returns for what they type,
+/// starting with `query`. Drawn below the cursor on stderr so stdout stays
+/// clean. `None` if they cancel with Esc or Ctrl-C.
+pub fn pick(query: &str, rank: impl Fn(&str) -> Vec<String>) -> Result<Option<String>, String> {
+    if let Err(err) = terminal::enable_raw_mode() {
+        return Err(err.to_string());
+    }
+    let mut stderr = std::io::stderr();
+    let result = run(&mut stderr, query, rank);
+    let _ = queue!(stderr, MoveToColumn(0), Clear(ClearType::FromCursorDown));
+    let _ = stderr.flush();
+    let _ = terminal::disable_raw_mode();
+    result
+}
+
+fn run(
+    out: &mut impl Write,
+    query: &str,
+    rank: impl Fn(&str) -> Vec<String>,
+) -> Result<Option<String>, String> {
+    let mut state = State::new(query, &rank);
+    loop {
+        if let Err(err) = draw(out, &state) {
+            return Err(err.to_string());
+        }
+        let key = match event::read() {
+            Ok(Event::Key(v)) if v.kind == KeyEventKind::Press => v,
+            Ok(_) => continue,
+            Err(err) => return Err(err.to_string()),
+        };
+        if let Some(picked) = state.key(key, &rank) {
+            return Ok(picked);
+        }
+    }
+}
+
+fn draw(out: &mut impl Write, state: &State) -> std::io::Result<()> {
+    queue!(
+        out,
+        MoveToColumn(0),
+        Clear(ClearType::FromCursorDown),
+        Print(PROMPT),
+        Print(&state.query)
+    )?;
+
+    let start = state.selected.saturating_sub(MAX_ITEMS - 1);
+    let shown = state.items.iter().enumerate().skip(start).take(MAX_ITEMS);
+    let mut lines = 0;
+    for (i, item) in shown {
+        out.queue(Print("\r\n"))?;
+        if i == state.selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("> {}", item)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            out.queue(Print(format!("  {}", item)))?;
+        }
+        lines += 1;
+    }
+    if state.items.is_empty() {
+        out.queue(Print("\r\n  No matches"))?;
+        lines += 1;
+    }
+
+    let column = PROMPT.chars().count() + state.query.chars().count();
+    queue!(out, MoveUp(lines), MoveToColumn(column as u16))?;
+    out.flush()
+}
+
+/// What is typed and what it matches, apart from drawing so it can be tested.
+struct State {
+    query: String,
+    items: Vec<String>,
+    selected: usize,
+}
+
+impl State {
+    fn new(query: &str, rank: impl Fn(&str) -> Vec<String>) -> State {
+        State {
+            query: query.to_string(),
+            items: rank(query),
+            selected: 0,
+        }
+    }
+
+    /// Apply a key press, `Some` once the user picked or cancelled.
+    fn key(&mut self, key: KeyEvent, rank: impl Fn(&str) -> Vec<String>) -> Option<Option<String>> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Some(None),
+            KeyCode::Char('c') if ctrl => return Some(None),
+            KeyCode::Enter => {
+                if let Some(v) = self.items.get(self.selected) {
+                    return Some(Some(v.clone()));
+                }
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.down(),
+            KeyCode::Char('n') if ctrl => self.down(),
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.filter(rank);
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.filter(rank);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn down(&mut self) {
+        if self.selected + 1 < self.items.len() {
+            self.selected += 1;
+        }
+    }
+
+    fn filter(&mut self, rank: impl Fn(&str) -> Vec<String>) {
+        self.items = rank(&self.query);
+        self.selected = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank(query: &str) -> Vec<String> {
+        ["github", "gitlab", "mail"]
+            .into_iter()
+            .filter(|v| v.contains(query))
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_state_filters_and_picks() {
+        let mut state = State::new("gi", rank);
+        assert_eq!(vec!["github", "gitlab"], state.items);
+
+        assert_eq!(None, state.key(press(KeyCode::Down), rank));
+        assert_eq!(None, state.key(press(KeyCode::Down), rank));
+        assert_eq!(1, state.selected);
+        assert_eq!(None, state.key(press(KeyCode::Up), rank));
+        assert_eq!(0, state.selected);
+
+        state.key(press(KeyCode::Char('t')), rank);
+        state.key(press(KeyCode::Char('l')), rank);
+        assert_eq!(vec!["gitlab"], state.items);
+        state.key(press(KeyCode::Backspace), rank);
+        assert_eq!(2, state.items.len());
+
+        state.key(press(KeyCode::Char('z')), rank);
+        assert!(state.items.is_empty());
+        assert_eq!(None, state.key(press(KeyCode::Enter), rank));
+        state.key(press(KeyCode::Backspace), rank);
+        state.key(press(KeyCode::Tab), rank);
+        assert_eq!(
+            Some(Some("gitlab".to_string())),
+            state.key(press(KeyCode::Enter), rank)
+        );
+    }
+
+    #[test]
+    fn test_state_cancels() {
+        let mut state = State::new("", rank);
+        assert_eq!(Some(None), state.key(press(KeyCode::Esc), rank));
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(Some(None), state.key(ctrl_c, rank));
+        assert_eq!(3, state.items.len());
+    }
+}
@@ -1,111 +1,387 @@
-use crate::text;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::password::Generator;
+use crate::search;
+use crate::secret::Secret;
+
+/// Marks the start of a record in the legacy line based format.
+pub const LEGACY_MARKER: &str = "resource";
+pub const FORMAT_VERSION: u32 = 1;
+/// Number of replaced passwords kept per resource.
+pub const HISTORY_LEN: usize = 10;
 
 pub enum Key {
     Name,
     User,
     Password,
+    Url,
+    Notes,
+    Tags,
+    Otp,
+    /// A custom field by its name.
+    Field(String),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Instance {
     pub name: String,
     pub user: String,
-    pub password: String,
+    pub password: Secret,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// TOTP secret in base32 or as an `otpauth://` URI.
+    #[serde(default, skip_serializing_if = "Secret::is_empty")]
+    pub otp: Secret,
+    /// Extra named values, e.g security question answers or API key IDs.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Secret>,
+    /// When the password was last set, in seconds since the Unix epoch.
+    /// 0 if unknown, e.g for resources imported or created before it was kept.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub changed: u64,
+    /// How to generate its passwords, e.g to meet the rules of the site.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<Generator>,
+    /// Replaced passwords, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<OldPassword>,
 }
 
-impl std::fmt::Display for Instance {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "resource\n{}\n{}\n{}\n",
-            &self.name, &self.user, &self.password
-        )
-    }
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OldPassword {
+    pub password: Secret,
+    /// When it was replaced, in seconds since the Unix epoch.
+    pub replaced: u64,
 }
 
-pub fn get(name: &str, content: &str) -> Result<Instance, String> {
-    let lines: Vec<&str> = content.lines().collect();
-    for (i, _) in lines.iter().enumerate() {
-        if lines[i] == text::RESERVED_RESOURCE && lines[i + 1] == name {
-            return Ok(Instance {
-                name: lines[i + 1].to_string(),
-                user: lines[i + 2].to_string(),
-                password: lines[i + 3].to_string(),
-            });
-        }
+fn is_zero(v: &u64) -> bool {
+    *v == 0
+}
+
+/// Notes and field values may hold secrets as well, so only their presence shows.
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instance")
+            .field("name", &self.name)
+            .field("user", &self.user)
+            .field("password", &self.password)
+            .field("url", &self.url)
+            .field("notes", &Redacted(!self.notes.is_empty()))
+            .field("tags", &self.tags)
+            .field("otp", &self.otp)
+            .field("fields", &self.fields)
+            .field("changed", &self.changed)
+            .field("generator", &self.generator)
+            .field("history", &self.history)
+            .finish()
     }
-    Err("Resource not found".to_string())
 }
 
-pub struct UpdateInput {
-    pub key: Key,
-    pub val: String,
+struct Redacted(bool);
 
-    pub name: String,
-    pub content: String,
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            true => f.write_str("[REDACTED]"),
+            false => f.write_str("\"\""),
+        }
+    }
 }
 
-pub fn update(input: UpdateInput) -> Result<String, String> {
-    let mut target_idx = 1;
+/// Narrows down `list`, empty criteria match everything.
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Exact, case-insensitive tag.
+    pub tag: Option<String>,
+    /// Case-insensitive substring of the URL.
+    pub url: Option<String>,
+}
 
-    match input.key {
-        Key::Name => (),
-        Key::User => target_idx += 1,
-        Key::Password => target_idx += 2,
-    };
+impl Filter {
+    pub fn matches(&self, resource: &Instance) -> bool {
+        if let Some(tag) = &self.tag {
+            if !resource.tags.iter().any(|v| v.eq_ignore_ascii_case(tag)) {
+                return false;
+            }
+        }
+        if let Some(url) = &self.url {
+            if !resource.url.to_lowercase().contains(&url.to_lowercase()) {
+                return false;
+            }
+        }
+        true
+    }
+}
 
-    let mut lines: Vec<String> = input.content.lines().map(|s| s.to_string()).collect();
-    for (i, _) in lines.iter().enumerate() {
-        if lines[i] == text::RESERVED_RESOURCE && lines[i + 1] == input.name {
-            lines[target_idx + i] = input.val;
-            break;
+/// Split a comma separated list of tags, dropping empty and duplicate ones.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = vec![];
+    for v in input.split(',') {
+        let v = v.trim();
+        if !v.is_empty() && !tags.iter().any(|t| t == v) {
+            tags.push(v.to_string());
         }
     }
+    tags
+}
 
-    Ok(lines.join("\n").to_string())
+/// The decrypted content of the file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Vault {
+    version: u32,
+    resources: Vec<Instance>,
 }
 
-pub fn delete(name: &str, content: String) -> Result<String, String> {
-    let mut name_idx = 0;
-    let mut user_idx = 0;
-    let mut pw_idx = 0;
-    let mut result: Vec<&str> = vec![];
+/// Parse the decrypted content into resources.
+///
+/// The content is a JSON document. Files written by older versions hold
+/// four line records instead, each starting with a `resource` line:
+///
+/// ```text
+/// resource
+/// <name>
+/// <user>
+/// <password>
+/// ```
+///
+/// Those are still read, and are written back as JSON on the next change.
+pub fn parse(content: &str) -> Result<Vec<Instance>, String> {
+    if content.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    if !content.trim_start().starts_with('{') {
+        return parse_legacy(content);
+    }
+
+    let vault: Vault = match serde_json::from_str(content) {
+        Ok(v) => v,
+        Err(err) => return Err(format!("malformed file content: {}", err)),
+    };
+    if vault.version > FORMAT_VERSION {
+        return Err(format!(
+            "content format version {} is not supported by this version of onepass - please upgrade",
+            vault.version
+        ));
+    }
+    Ok(vault.resources)
+}
 
+fn parse_legacy(content: &str) -> Result<Vec<Instance>, String> {
     let lines: Vec<&str> = content.lines().collect();
-    for (i, v) in lines.iter().enumerate() {
-        if lines[i] == "\n" {
+    let mut result: Vec<Instance> = vec![];
+
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].is_empty() {
+            i += 1;
             continue;
         }
-        if lines[i] == text::RESERVED_RESOURCE && lines[i + 1] == name {
-            name_idx = i + 1;
-            user_idx = i + 2;
-            pw_idx = i + 3;
-            continue;
+        if lines[i] != LEGACY_MARKER {
+            return Err(format!("malformed resource at line {}", i + 1));
         }
-        if i == name_idx && name_idx != 0 {
-            continue;
+        if i + 3 >= lines.len() {
+            return Err(format!("unfinished resource at line {}", i + 1));
         }
-        if i == user_idx && user_idx != 0 {
-            continue;
+        result.push(Instance {
+            name: lines[i + 1].to_string(),
+            user: lines[i + 2].to_string(),
+            password: Secret::from(lines[i + 3]),
+            ..Default::default()
+        });
+        i += 4;
+    }
+
+    Ok(result)
+}
+
+/// Serialize resources into the content to encrypt.
+pub fn serialize(resources: &[Instance]) -> Result<Secret, String> {
+    let vault = Vault {
+        version: FORMAT_VERSION,
+        resources: resources.to_vec(),
+    };
+    match serde_json::to_string(&vault) {
+        Ok(v) => Ok(Secret::new(v)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub fn get(name: &str, content: &str) -> Result<Instance, String> {
+    let resources = parse(content)?;
+    for v in resources {
+        if v.name == name {
+            return Ok(v);
         }
-        if i == pw_idx && pw_idx != 0 {
-            continue;
+    }
+    Err("Resource not found".to_string())
+}
+
+/// Resources matching the filter, in the order they were added.
+pub fn list(content: &str, filter: &Filter) -> Result<Vec<Instance>, String> {
+    let resources = parse(content)?;
+    Ok(resources
+        .into_iter()
+        .filter(|v| filter.matches(v))
+        .collect())
+}
+
+/// Resources matching the query, best match first.
+pub fn find(content: &str, query: &str) -> Result<Vec<Instance>, String> {
+    Ok(rank(parse(content)?, query))
+}
+
+/// Order resources by how well they match the query, dropping the ones
+/// that do not match at all. The name counts most, then tags, URL and user.
+pub fn rank(resources: Vec<Instance>, query: &str) -> Vec<Instance> {
+    let mut scored: Vec<(i64, Instance)> = resources
+        .into_iter()
+        .filter_map(|v| {
+            let fields = [(&v.name, 3), (&v.url, 1), (&v.user, 1)];
+            let best = fields
+                .into_iter()
+                .chain(v.tags.iter().map(|t| (t, 2)))
+                .filter_map(|(text, weight)| search::score(query, text).map(|s| s * weight))
+                .max()?;
+            Some((best, v))
+        })
+        .collect();
+    // Stable, so equally good matches keep the order they were added in.
+    scored.sort_by_key(|v| std::cmp::Reverse(v.0));
+    scored.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Append a resource, names must be unique.
+pub fn add(mut resource: Instance, content: &str) -> Result<Secret, String> {
+    let mut resources = parse(content)?;
+    if resources.iter().any(|v| v.name == resource.name) {
+        return Err("Resource already exists".to_string());
+    }
+    if resource.changed == 0 {
+        resource.changed = now();
+    }
+    resources.push(resource);
+    serialize(&resources)
+}
+
+pub struct UpdateInput {
+    pub key: Key,
+    pub val: Secret,
+
+    pub name: String,
+    pub content: Secret,
+}
+
+pub fn update(input: UpdateInput) -> Result<Secret, String> {
+    let mut resources = parse(input.content.expose())?;
+    let val = input.val.expose();
+
+    if let Key::Name = input.key {
+        if val != input.name && resources.iter().any(|v| v.name == val) {
+            return Err("Resource already exists".to_string());
         }
-        result.push(v);
     }
 
-    if name_idx == 0 {
-        return Err("Resource not found".to_string());
+    let target = match resources.iter_mut().find(|v| v.name == input.name) {
+        Some(v) => v,
+        None => return Err("Resource not found".to_string()),
+    };
+    match input.key {
+        Key::Name => target.name = val.to_string(),
+        Key::User => target.user = val.to_string(),
+        Key::Password => set_password(target, input.val.clone()),
+        Key::Url => target.url = val.to_string(),
+        Key::Notes => target.notes = val.to_string(),
+        Key::Tags => target.tags = parse_tags(val),
+        Key::Otp => target.otp = input.val.clone(),
+        Key::Field(field) => {
+            // An empty value removes the field.
+            if val.is_empty() {
+                target.fields.remove(&field);
+            } else {
+                target.fields.insert(field, input.val.clone());
+            }
+        }
+    };
+
+    serialize(&resources)
+}
+
+/// Replace the password of a resource with one made by `generator`,
+/// which is kept for the next time.
+pub fn rotate(
+    name: &str,
+    password: Secret,
+    generator: Generator,
+    content: &str,
+) -> Result<Secret, String> {
+    let mut resources = parse(content)?;
+    let target = match resources.iter_mut().find(|v| v.name == name) {
+        Some(v) => v,
+        None => return Err("Resource not found".to_string()),
+    };
+    set_password(target, password);
+    target.generator = Some(generator);
+    serialize(&resources)
+}
+
+/// Keeps the replaced password in the history, unless there was none.
+fn set_password(target: &mut Instance, password: Secret) {
+    let old = std::mem::replace(&mut target.password, password);
+    if !old.is_empty() && old != target.password {
+        target.history.push(OldPassword {
+            password: old,
+            replaced: now(),
+        });
+        let excess = target.history.len().saturating_sub(HISTORY_LEN);
+        target.history.drain(..excess);
     }
+    target.changed = now();
+}
 
-    Ok(result.join("\n").to_string())
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(v) => v.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+pub fn delete(name: &str, content: &str) -> Result<Secret, String> {
+    let mut resources = parse(content)?;
+    let len = resources.len();
+    resources.retain(|v| v.name != name);
+    if resources.len() == len {
+        return Err("Resource not found".to_string());
+    }
+    serialize(&resources)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn seed(amount: u8) -> String {
+    fn seed(amount: u8) -> Secret {
+        let mut resources = vec![];
+        for i in 0..amount {
+            resources.push(Instance {
+                name: format!("name{}", i),
+                user: format!("user{}", i),
+                password: Secret::new(format!("password{}", i)),
+                ..Default::default()
+            });
+        }
+        serialize(&resources).expect("serializing")
+    }
+
+    fn seed_legacy(amount: u8) -> Secret {
         let mut content = String::new();
         for i in 0..amount {
             content.push_str("resource\n");
@@ -113,7 +389,7 @@ mod tests {
             content.push_str(format!("user{}\n", i).as_str());
             content.push_str(format!("password{}\n", i).as_str());
         }
-        content
+        Secret::new(content)
     }
 
     #[test]
@@ -121,47 +397,112 @@ mod tests {
         let name = "twitter";
         let user = "user@email.com";
         let password = "password";
-        let content = format!("resource\n{}\n{}\n{}\n", name, user, password);
+        let content = serialize(&[Instance {
+            name: name.to_string(),
+            user: user.to_string(),
+            password: Secret::from(password),
+            ..Default::default()
+        }])
+        .expect("serializing");
 
-        let resource = get("twitter", &content).expect("getting");
+        let resource = get("twitter", content.expose()).expect("getting");
         assert_eq!(resource.name, name);
         assert_eq!(resource.user, user);
-        assert_eq!(resource.password, password);
+        assert_eq!(resource.password.expose(), password);
 
-        let err_result = get("does-not-exist", &content);
+        let err_result = get("does-not-exist", content.expose());
         assert_eq!(err_result.unwrap_err(), "Resource not found");
     }
 
     #[test]
     fn test_get_many() {
-        let mut long_content = String::new();
-        let target_name = "website";
-        let target_user = "website@user.com";
-        let target_password = "websitepass";
-        for i in 1..100 {
-            if i == 50 {
-                long_content.push_str("resource\n");
-                long_content.push_str(format!("{}\n", target_name).as_str());
-                long_content.push_str(format!("{}\n", target_user).as_str());
-                long_content.push_str(format!("{}\n", target_password).as_str());
-                continue;
-            }
-            long_content.push_str("resource\n");
-            long_content.push_str(format!("name{}\n", i).as_str());
-            long_content.push_str(format!("user{}\n", i).as_str());
-            long_content.push_str(format!("password{}\n", i).as_str());
-        }
-        let long_result = get("website", &long_content).expect("getting long result");
-        assert_eq!(long_result.name, target_name);
-        assert_eq!(long_result.user, target_user);
-        assert_eq!(long_result.password, target_password);
+        let content = seed(100);
+        let long_result = get("name50", content.expose()).expect("getting long result");
+        assert_eq!(long_result.name, "name50");
+        assert_eq!(long_result.user, "user50");
+        assert_eq!(long_result.password.expose(), "password50");
     }
 
     #[test]
-    #[should_panic]
-    fn test_get_panics_on_unfinished_resource() {
+    fn test_get_legacy() {
+        let content = seed_legacy(3);
+        let resource = get("name1", content.expose()).expect("getting");
+        assert_eq!(resource.user, "user1");
+        assert_eq!(resource.password.expose(), "password1");
+        let names: Vec<String> = list(content.expose(), &Filter::default())
+            .expect("listing")
+            .into_iter()
+            .map(|v| v.name)
+            .collect();
+        assert_eq!(vec!["name0", "name1", "name2"], names);
+    }
+
+    #[test]
+    fn test_parse_rejects_unfinished_legacy_resource() {
         let content = format!("resource\n{}\n{}\n", "name", "password");
-        get("twitter", &content).expect("getting");
+        assert_eq!(
+            "unfinished resource at line 1",
+            get("name", &content).unwrap_err()
+        );
+        assert_eq!(
+            "malformed resource at line 5",
+            parse("resource\na\nb\nc\nnope\n").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_special_values_roundtrip() {
+        let tricky = Instance {
+            name: "resource".to_string(),
+            user: "line\nbreak".to_string(),
+            password: Secret::from("{\"quoted\"}\n\nresource"),
+            notes: "multi\nline".to_string(),
+            changed: 1_700_000_000,
+            ..Default::default()
+        };
+        let content = add(tricky.clone(), seed(2).expose()).expect("adding");
+        assert_eq!(tricky, get("resource", content.expose()).expect("getting"));
+        assert_eq!(
+            3,
+            list(content.expose(), &Filter::default())
+                .expect("listing")
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let mut resource = Instance {
+            name: "mail".to_string(),
+            password: Secret::from("hunter2"),
+            notes: "recovery code 1234".to_string(),
+            otp: Secret::from("GEZDGNBV"),
+            ..Default::default()
+        };
+        resource
+            .fields
+            .insert("pin".to_string(), Secret::from("0000"));
+        let debug = format!("{:?}", resource);
+        assert!(debug.contains("mail"));
+        assert!(debug.contains("pin"));
+        for secret in ["hunter2", "1234", "GEZDGNBV", "0000"] {
+            assert!(!debug.contains(secret), "{} leaked", secret);
+        }
+    }
+
+    #[test]
+    fn test_add_duplicate() {
+        let content = seed(2);
+        let duplicate = Instance {
+            name: "name1".to_string(),
+            user: "u".to_string(),
+            password: Secret::from("p"),
+            ..Default::default()
+        };
+        assert_eq!(
+            "Resource already exists",
+            add(duplicate, content.expose()).unwrap_err()
+        );
     }
 
     #[test]
@@ -170,15 +511,23 @@ mod tests {
         let content = seed(3);
         let updated = update(UpdateInput {
             key: Key::Name,
-            val: String::from(new_value),
+            val: Secret::from(new_value),
             name: String::from("name2"),
             content,
         })
         .expect("updating");
-        let lines: Vec<&str> = updated.lines().collect();
-        assert_eq!(lines[9], new_value);
-        assert_eq!(lines[10], "user2");
-        assert_eq!(lines[11], "password2");
+        let got = get(new_value, updated.expose()).expect("getting");
+        assert_eq!(got.user, "user2");
+        assert_eq!(got.password.expose(), "password2");
+        assert!(get("name2", updated.expose()).is_err());
+
+        let err = update(UpdateInput {
+            key: Key::Name,
+            val: Secret::from("name0"),
+            name: String::from(new_value),
+            content: updated,
+        });
+        assert_eq!("Resource already exists", err.unwrap_err());
     }
 
     #[test]
@@ -187,46 +536,216 @@ mod tests {
         let content = seed(3);
         let updated = update(UpdateInput {
             key: Key::User,
-            val: String::from(new_value),
+            val: Secret::from(new_value),
             name: String::from("name0"),
             content,
         })
         .expect("updating");
-        let lines: Vec<&str> = updated.lines().collect();
-        assert_eq!(lines[1], "name0");
-        assert_eq!(lines[2], new_value);
-        assert_eq!(lines[3], "password0");
+        let got = get("name0", updated.expose()).expect("getting");
+        assert_eq!(got.user, new_value);
+        assert_eq!(got.password.expose(), "password0");
     }
 
     #[test]
     fn test_update_password() {
         let new_value = "new_password";
-        let content = seed(3);
+        let content = seed_legacy(3);
         let updated = update(UpdateInput {
             key: Key::Password,
-            val: String::from(new_value),
+            val: Secret::from(new_value),
             name: String::from("name1"),
             content,
         })
         .expect("updating");
-        let lines: Vec<&str> = updated.lines().collect();
-        assert_eq!(lines[5], "name1");
-        assert_eq!(lines[6], "user1");
-        assert_eq!(lines[7], new_value);
+        assert!(updated.expose().starts_with('{'));
+        let got = get("name1", updated.expose()).expect("getting");
+        assert_eq!(got.user, "user1");
+        assert_eq!(got.password.expose(), new_value);
+        assert!(got.changed > 0);
+
+        let not_found = update(UpdateInput {
+            key: Key::Password,
+            val: Secret::from(new_value),
+            name: String::from("non"),
+            content: updated,
+        });
+        assert_eq!(not_found.unwrap_err(), "Resource not found");
+    }
+
+    #[test]
+    fn test_rotate_keeps_history() {
+        let generator = Generator::Password(crate::password::Policy {
+            length: 24,
+            ..Default::default()
+        });
+        let mut content = seed(2);
+        for i in 0..HISTORY_LEN + 2 {
+            let password = Secret::new(format!("rotated{}", i));
+            content =
+                rotate("name1", password, generator.clone(), content.expose()).expect("rotating");
+        }
+        let got = get("name1", content.expose()).expect("getting");
+        assert_eq!(got.password.expose(), format!("rotated{}", HISTORY_LEN + 1));
+        assert_eq!(got.generator, Some(generator.clone()));
+        assert_eq!(got.history.len(), HISTORY_LEN);
+        assert_eq!(got.history[0].password.expose(), "rotated1");
+        assert_eq!(
+            got.history[HISTORY_LEN - 1].password.expose(),
+            format!("rotated{}", HISTORY_LEN)
+        );
+
+        // Updating the password by hand keeps the history too, but not
+        // setting the same one again.
+        for _ in 0..2 {
+            content = update(UpdateInput {
+                key: Key::Password,
+                val: Secret::from("typed"),
+                name: String::from("name0"),
+                content,
+            })
+            .expect("updating");
+        }
+        let got = get("name0", content.expose()).expect("getting");
+        assert_eq!(got.generator, None);
+        assert_eq!(got.history.len(), 1);
+        assert_eq!(got.history[0].password.expose(), "password0");
+
+        let not_found = rotate("non", Secret::from("x"), generator, content.expose());
+        assert_eq!(not_found.unwrap_err(), "Resource not found");
+    }
+
+    #[test]
+    fn test_update_extra_fields() {
+        let mut content = seed(2);
+        for (key, val) in [
+            (Key::Url, "https://example.com/login"),
+            (Key::Notes, "recovery codes in the safe"),
+            (Key::Tags, "work, email,,work"),
+            (Key::Field("pin".to_string()), "1234"),
+            (Key::Field("question".to_string()), "blue"),
+            (Key::Field("question".to_string()), ""),
+        ] {
+            content = update(UpdateInput {
+                key,
+                val: Secret::from(val),
+                name: String::from("name1"),
+                content,
+            })
+            .expect("updating");
+        }
+
+        let got = get("name1", content.expose()).expect("getting");
+        assert_eq!("https://example.com/login", got.url);
+        assert_eq!("recovery codes in the safe", got.notes);
+        assert_eq!(vec!["work", "email"], got.tags);
+        assert_eq!(1, got.fields.len());
+        assert_eq!("1234", got.fields["pin"].expose());
+
+        let untouched = get("name0", content.expose()).expect("getting");
+        assert!(untouched.url.is_empty() && untouched.tags.is_empty());
+    }
+
+    #[test]
+    fn test_list_filter() {
+        let mut resources = vec![];
+        for (name, tags, url) in [
+            ("mail", "work,email", "https://mail.example.com"),
+            ("bank", "finance", "https://bank.example.org"),
+            ("chat", "work", ""),
+        ] {
+            resources.push(Instance {
+                name: name.to_string(),
+                tags: parse_tags(tags),
+                url: url.to_string(),
+                ..Default::default()
+            });
+        }
+        let content = serialize(&resources).expect("serializing");
+
+        let fn_names = |filter: Filter| -> Vec<String> {
+            list(content.expose(), &filter)
+                .expect("listing")
+                .into_iter()
+                .map(|v| v.name)
+                .collect()
+        };
+        assert_eq!(3, fn_names(Filter::default()).len());
+        assert_eq!(
+            vec!["mail", "chat"],
+            fn_names(Filter {
+                tag: Some("WORK".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec!["mail", "bank"],
+            fn_names(Filter {
+                url: Some("example".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec!["mail"],
+            fn_names(Filter {
+                tag: Some("work".to_string()),
+                url: Some("mail".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        let mut resources = vec![];
+        for (name, user, url, tags) in [
+            ("mail", "me@example.com", "https://mail.example.com", "work"),
+            ("github", "octocat", "https://github.com", "code"),
+            ("gitlab", "me", "https://gitlab.com", "code,work"),
+        ] {
+            resources.push(Instance {
+                name: name.to_string(),
+                user: user.to_string(),
+                url: url.to_string(),
+                tags: parse_tags(tags),
+                ..Default::default()
+            });
+        }
+        let content = serialize(&resources).expect("serializing");
+
+        let fn_names = |query: &str| -> Vec<String> {
+            find(content.expose(), query)
+                .expect("finding")
+                .into_iter()
+                .map(|v| v.name)
+                .collect()
+        };
+        assert_eq!(vec!["github", "gitlab"], fn_names("git"));
+        assert_eq!(vec!["gitlab"], fn_names("GitLab"));
+        assert_eq!(vec!["github"], fn_names("octo"));
+        assert_eq!(vec!["mail", "gitlab"], fn_names("work"));
+        assert_eq!(3, fn_names("").len());
+        assert!(fn_names("zzz").is_empty());
+    }
+
+    #[test]
+    fn test_parse_without_extra_fields() {
+        let content = r#"{"version":1,"resources":[{"name":"a","user":"b","password":"c"}]}"#;
+        let got = get("a", content).expect("getting");
+        assert!(got.url.is_empty() && got.notes.is_empty());
+        assert!(got.tags.is_empty() && got.fields.is_empty());
+        assert_eq!(content, serialize(&[got]).expect("serializing").expose());
     }
 
     #[test]
     fn test_delete() {
         let mut content = seed(3);
-        let deleted = delete("name0", content).expect("deleting");
-        let lines: Vec<&str> = deleted.lines().collect();
-        assert_eq!(lines.len(), 8);
-        assert!(!lines.contains(&"name0"));
-        assert!(!lines.contains(&"user0"));
-        assert!(!lines.contains(&"password0"));
+        let deleted = delete("name0", content.expose()).expect("deleting");
+        let list = list(deleted.expose(), &Filter::default()).expect("listing");
+        assert_eq!(2, list.len());
+        assert_eq!("name1", list[0].name);
+        assert_eq!("name2", list[1].name);
 
         content = seed(3);
-        let not_found = delete("non", content);
+        let not_found = delete("non", content.expose());
         assert_eq!(not_found.unwrap_err(), "Resource not found");
     }
 }
@@ -0,0 +1,96 @@
+/// Characters after which a new word starts, e.g in `my-bank.com`.
+const SEPARATORS: &[char] = &[' ', '-', '_', '.', '/', '@', ':'];
+
+/// How well the query matches the text, case-insensitive. Higher is better,
+/// `None` if the characters of the query do not all appear in order.
+///
+/// An exact match beats a prefix, a prefix beats a substring, and a
+/// substring beats characters scattered over the text. Scattered matches
+/// score higher the closer together they are and the more of them start a word.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+    if query.len() > text.len() {
+        return None;
+    }
+    let extra = (text.len() - query.len()) as i64;
+
+    if query == text {
+        return Some(1000);
+    }
+    if text.starts_with(&query) {
+        return Some(800 - extra.min(100));
+    }
+    if let Some(pos) = text.windows(query.len()).position(|w| w == query) {
+        let bonus = if SEPARATORS.contains(&text[pos - 1]) {
+            50
+        } else {
+            0
+        };
+        return Some(600 + bonus - (pos as i64).min(100));
+    }
+
+    let mut score: i64 = 300;
+    let mut q = 0;
+    let mut last: Option<usize> = None;
+    for (i, c) in text.iter().enumerate() {
+        if q == query.len() {
+            break;
+        }
+        if *c != query[q] {
+            continue;
+        }
+        if i == 0 || SEPARATORS.contains(&text[i - 1]) {
+            score += 10;
+        }
+        match last {
+            Some(l) => score -= (i - l - 1) as i64,
+            None => score -= (i as i64).min(20),
+        }
+        last = Some(i);
+        q += 1;
+    }
+    if q < query.len() {
+        return None;
+    }
+    Some(score.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_order() {
+        let exact = score("github", "GitHub").expect("matching");
+        let prefix = score("git", "github").expect("matching");
+        let substring = score("hub", "github").expect("matching");
+        let scattered = score("gthb", "github").expect("matching");
+        assert!(exact > prefix);
+        assert!(prefix > substring);
+        assert!(substring > scattered);
+        assert!(scattered > 0);
+    }
+
+    #[test]
+    fn test_score_no_match() {
+        assert_eq!(None, score("bank", "github"));
+        assert_eq!(None, score("hubgit", "github"));
+        assert_eq!(None, score("longer than text", "text"));
+        assert_eq!(Some(0), score("", "anything"));
+    }
+
+    #[test]
+    fn test_score_prefers_word_starts_and_close_matches() {
+        assert!(
+            score("mb", "my-bank").expect("matching") > score("mb", "mobile").expect("matching")
+        );
+        assert!(
+            score("mail", "my mail").expect("matching") > score("mail", "gmail").expect("matching")
+        );
+        assert!(score("ab", "a_b").expect("matching") > score("ab", "a____b").expect("matching"));
+    }
+}
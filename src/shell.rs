@@ -0,0 +1,335 @@
+use std::io::Stdin;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::cli;
+use crate::command;
+use crate::config;
+use crate::file;
+use crate::input;
+use crate::kdf;
+use crate::resource;
+use crate::tui::Settings;
+
+const PROMPT: &str = "onepass> ";
+
+/// Commands of the shell and whether they take a resource name.
+const COMMANDS: &[(&str, bool)] = &[
+    ("get", true),
+    ("otp", true),
+    ("list", false),
+    ("new", false),
+    ("update", true),
+    ("rotate", true),
+    ("del", true),
+    ("suggest", false),
+    ("lock", false),
+    ("help", false),
+    ("exit", false),
+];
+
+const HELP: &str = "\
+get <name>               show a resource and copy its password
+otp <name>               copy the current one-time code of a resource
+list [tag]               list resources, optionally only those with the tag
+new [options]            create a new resource, offering a password of the `suggest` options
+update <name>            update a resource
+rotate <name> [options]  replace the password of a resource with a generated one
+del <name>               delete a resource
+suggest [options]        suggest a new strong password, see `onepass suggest --help`
+lock                     forget the key until the next command
+exit                     leave the shell, so does Ctrl-D";
+
+/// What the shell keeps while unlocked. Shared with the thread that
+/// locks it after `lock_after` without input.
+struct Session {
+    credential: Option<kdf::Credential>,
+    /// Resource names for completion, cleared with the credential.
+    names: Vec<String>,
+    last_input: Instant,
+}
+
+/// Read and run commands until the user exits. The file is decrypted for
+/// every command, so changes from elsewhere show up, and only written by
+/// the commands that change it.
+pub fn run(
+    custom_path: Option<&str>,
+    credential: kdf::Credential,
+    settings: Settings,
+    stdin: &mut Stdin,
+) -> Result<(), String> {
+    let session = Arc::new(Mutex::new(Session {
+        credential: Some(credential),
+        names: vec![],
+        last_input: Instant::now(),
+    }));
+    reload_names(custom_path, &mut lock(&session));
+    if !settings.lock_after.is_zero() {
+        let weak = Arc::downgrade(&session);
+        std::thread::spawn(move || watch(weak, settings.lock_after));
+    }
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> = match Editor::new() {
+        Ok(v) => v,
+        Err(err) => return Err(err.to_string()),
+    };
+    editor.set_helper(Some(ShellHelper {
+        session: session.clone(),
+    }));
+
+    println!("Type `help` for the commands, `exit` or Ctrl-D to leave");
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(v) => v,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(err.to_string()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+
+        let (cmd, arg) = match line.split_once(' ') {
+            Some((c, a)) => (c, a.trim()),
+            None => (line, ""),
+        };
+        match cmd {
+            "exit" | "quit" => return Ok(()),
+            "help" => println!("{}", HELP),
+            "suggest" => match cli::parse_generator(arg).and_then(|v| v.generator().generate()) {
+                Ok(v) => println!("{}", v),
+                Err(err) => eprintln!("{}", err),
+            },
+            "lock" => {
+                let mut session = lock(&session);
+                session.credential = None;
+                session.names.clear();
+            }
+            _ => {
+                if let Err(err) = execute(custom_path, &session, &settings, stdin, cmd, arg) {
+                    eprintln!("{}", err);
+                }
+            }
+        }
+        lock(&session).last_input = Instant::now();
+    }
+}
+
+/// Run a command that needs the file, unlocking it again if needed.
+fn execute(
+    custom_path: Option<&str>,
+    session: &Mutex<Session>,
+    settings: &Settings,
+    stdin: &mut Stdin,
+    cmd: &str,
+    arg: &str,
+) -> Result<(), String> {
+    let takes_name = match COMMANDS.iter().find(|(c, _)| *c == cmd) {
+        Some((_, v)) => *v,
+        None => return Err(format!("Unknown command `{}` - try `help`", cmd)),
+    };
+    if takes_name && arg.is_empty() {
+        return Err(format!("Usage: {} <name>", cmd));
+    }
+
+    // The thread that locks the session must not wait for prompts, so the
+    // credential is taken out while the command runs.
+    let taken = lock(session).credential.take();
+    let unlocked = taken.is_none();
+    let credential = match taken {
+        Some(v) => v,
+        None => {
+            println!("The shell is locked");
+            let password = input::master_password()?;
+            file::unlock(custom_path, password.expose())?
+        }
+    };
+    let result = match cmd {
+        "get" => command::pick_resource(custom_path, &credential, arg)
+            .map(|v| command::show_resource(&v, settings.clipboard_timeout)),
+        "otp" => command::pick_resource(custom_path, &credential, arg)
+            .and_then(|v| command::otp_code(&v))
+            .map(|(code, remaining)| {
+                println!("Valid for {}s", remaining);
+                command::copy("Code", &code, settings.clipboard_timeout);
+            }),
+        "list" => {
+            let mut filter = resource::Filter::default();
+            if !arg.is_empty() {
+                filter.tag = Some(arg.to_string());
+            }
+            command::list_resources(custom_path, &credential, &filter).map(|v| {
+                if v.is_empty() {
+                    println!("No resources");
+                } else {
+                    command::print_resources(&v);
+                }
+            })
+        }
+        "new" => config::load()
+            .and_then(|config| {
+                let generator = cli::parse_generator(arg)?.generator();
+                input::resource(stdin, &generator, config.breach_db.as_deref())
+            })
+            .and_then(|v| command::new_resource(custom_path, &credential, v)),
+        "update" => input::update_resource(stdin).and_then(|(key, val)| {
+            command::update_resource(custom_path, &credential, arg.to_string(), key, val)
+        }),
+        "rotate" => {
+            // Names may hold spaces, so the options start at the first `--`.
+            let (name, options) = match arg.find(" --") {
+                Some(i) => (arg[..i].trim_end(), &arg[i..]),
+                None => (arg, ""),
+            };
+            let timeout = settings.clipboard_timeout;
+            cli::parse_generator(options).and_then(|generator| {
+                let got = command::pick_resource(custom_path, &credential, name)?;
+                let custom = generator.custom();
+                command::rotate_resource(custom_path, &credential, got, custom, timeout, stdin)
+            })
+        }
+        "del" => command::delete_resource(custom_path, &credential, arg),
+        _ => Err(format!("Unknown command `{}` - try `help`", cmd)),
+    };
+
+    let mut session = lock(session);
+    session.credential = Some(credential);
+    if unlocked || matches!(cmd, "new" | "update" | "del") {
+        reload_names(custom_path, &mut session);
+    }
+    result
+}
+
+fn reload_names(custom_path: Option<&str>, session: &mut Session) {
+    let credential = match &session.credential {
+        Some(v) => v,
+        None => return,
+    };
+    let filter = resource::Filter::default();
+    if let Ok(v) = command::list_resources(custom_path, credential, &filter) {
+        session.names = v.into_iter().map(|v| v.name).collect();
+    }
+}
+
+/// Drop the credential once the session has been idle for `lock_after`.
+/// Stops when the shell exits.
+fn watch(session: Weak<Mutex<Session>>, lock_after: Duration) {
+    loop {
+        std::thread::sleep(Duration::from_secs(1));
+        let session = match session.upgrade() {
+            Some(v) => v,
+            None => return,
+        };
+        let mut session = lock(&session);
+        if session.credential.is_some() && session.last_input.elapsed() >= lock_after {
+            session.credential = None;
+            session.names.clear();
+        }
+    }
+}
+
+/// A panic while holding the lock leaves nothing half-written worth refusing.
+fn lock(session: &Mutex<Session>) -> MutexGuard<'_, Session> {
+    match session.lock() {
+        Ok(v) => v,
+        Err(err) => err.into_inner(),
+    }
+}
+
+/// Completions for the line up to the cursor: commands for the first word,
+/// resource names after commands that take one. Returns where the
+/// completed part starts.
+fn complete(line: &str, names: &[String]) -> (usize, Vec<String>) {
+    let (cmd, arg) = match line.split_once(' ') {
+        Some(v) => v,
+        None => {
+            let commands = COMMANDS
+                .iter()
+                .filter(|(c, _)| c.starts_with(line))
+                .map(|(c, _)| c.to_string())
+                .collect();
+            return (0, commands);
+        }
+    };
+    if !COMMANDS
+        .iter()
+        .any(|(c, takes_name)| *c == cmd && *takes_name)
+    {
+        return (line.len(), vec![]);
+    }
+    let arg = arg.trim_start();
+    let names = names
+        .iter()
+        .filter(|v| v.starts_with(arg))
+        .cloned()
+        .collect();
+    (line.len() - arg.len(), names)
+}
+
+struct ShellHelper {
+    session: Arc<Mutex<Session>>,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let session = lock(&self.session);
+        Ok(complete(&line[..pos], &session.names))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        let names = vec![
+            "github".to_string(),
+            "gitlab".to_string(),
+            "my bank".to_string(),
+        ];
+
+        assert_eq!((0, vec!["get".to_string()]), complete("ge", &names));
+        assert_eq!(COMMANDS.len(), complete("", &names).1.len());
+
+        assert_eq!(
+            (4, vec!["github".to_string(), "gitlab".to_string()]),
+            complete("get git", &names)
+        );
+        assert_eq!(
+            (5, vec!["my bank".to_string()]),
+            complete("del  my", &names)
+        );
+        assert_eq!((7, names.clone()), complete("update ", &names));
+
+        assert_eq!((8, vec![]), complete("suggest ", &names));
+        assert_eq!((7, vec![]), complete("nope gi", &names));
+    }
+}
@@ -0,0 +1,298 @@
+/// Passwords and words attackers try first, most common first, lowercase.
+const COMMON: &[&str] = &[
+    "password",
+    "123456",
+    "123456789",
+    "qwerty",
+    "12345678",
+    "111111",
+    "1234567",
+    "12345",
+    "1234",
+    "iloveyou",
+    "princess",
+    "admin",
+    "welcome",
+    "666666",
+    "abc123",
+    "football",
+    "123123",
+    "monkey",
+    "654321",
+    "charlie",
+    "aa123456",
+    "donald",
+    "password1",
+    "qwerty123",
+    "letmein",
+    "dragon",
+    "baseball",
+    "master",
+    "hello",
+    "freedom",
+    "whatever",
+    "qazwsx",
+    "trustno1",
+    "login",
+    "starwars",
+    "shadow",
+    "superman",
+    "michael",
+    "mustang",
+    "jennifer",
+    "hunter",
+    "batman",
+    "access",
+    "secret",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "sunshine",
+    "computer",
+    "internet",
+    "love",
+    "money",
+    "soccer",
+    "hockey",
+    "ranger",
+    "jordan",
+    "harley",
+    "robert",
+    "matthew",
+    "daniel",
+    "andrew",
+    "thomas",
+    "joshua",
+    "pepper",
+    "ginger",
+    "cheese",
+    "cookie",
+    "flower",
+    "orange",
+    "banana",
+    "apple",
+    "chocolate",
+    "maggie",
+    "buster",
+    "tigger",
+    "killer",
+    "george",
+    "pass",
+    "test",
+    "guest",
+    "root",
+    "changeme",
+    "default",
+    "onepass",
+    "secure",
+    "private",
+    "google",
+    "facebook",
+    "linkedin",
+    "github",
+    "email",
+    "mail",
+    "bank",
+];
+
+const KEYBOARD_ROWS: &[&str] = &[
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+
+/// Common l33t substitutions and the letters they stand for.
+const LEET: &[(char, char)] = &[
+    ('0', 'o'),
+    ('1', 'i'),
+    ('3', 'e'),
+    ('4', 'a'),
+    ('5', 's'),
+    ('7', 't'),
+    ('@', 'a'),
+    ('$', 's'),
+    ('!', 'i'),
+];
+
+/// How hard a password is to guess.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// log10 of the guesses an attacker who knows common patterns needs.
+    pub guesses_log10: f64,
+    /// 0 (guessed right away) to 4 (very hard to guess), like zxcvbn.
+    pub score: u8,
+}
+
+/// Estimate the guesses needed for a password, in the spirit of zxcvbn:
+/// the password is split into the cheapest sequence of common words,
+/// runs like `abcd` or `4321`, repeats, keyboard rows, years and single
+/// characters guessed by brute force, and their guesses multiplied.
+pub fn estimate(password: &str) -> Estimate {
+    let chars: Vec<char> = password.chars().collect();
+    let n = chars.len();
+    // best[j]: fewest guesses (log10) for the first j characters.
+    let mut best = vec![f64::INFINITY; n + 1];
+    best[0] = 0.0;
+    for i in 0..n {
+        if best[i].is_infinite() {
+            continue;
+        }
+        let brute = best[i] + brute_force(chars[i]).log10();
+        best[i + 1] = best[i + 1].min(brute);
+        for j in i + 2..=n {
+            if let Some(guesses) = pattern(&chars[i..j]) {
+                best[j] = best[j].min(best[i] + guesses.log10());
+            }
+        }
+    }
+
+    let guesses_log10 = best[n];
+    let score = match guesses_log10 {
+        v if v < 3.0 => 0,
+        v if v < 6.0 => 1,
+        v if v < 8.0 => 2,
+        v if v < 10.0 => 3,
+        _ => 4,
+    };
+    Estimate {
+        guesses_log10,
+        score,
+    }
+}
+
+/// Guesses for one character nothing else explains.
+fn brute_force(c: char) -> f64 {
+    match c {
+        '0'..='9' => 10.0,
+        'a'..='z' | 'A'..='Z' => 26.0,
+        c if c.is_ascii() => 33.0,
+        _ => 100.0,
+    }
+}
+
+/// Guesses for a run of characters matching a known pattern.
+fn pattern(run: &[char]) -> Option<f64> {
+    let len = run.len() as f64;
+    [
+        dictionary(run),
+        repeat(run).then(|| brute_force(run[0]) * len),
+        sequence(run).map(|descending| {
+            let base = match run[0] {
+                'a' | 'A' | '1' | '0' | 'z' | 'Z' | '9' => 4.0,
+                '0'..='9' => 10.0,
+                _ => 26.0,
+            };
+            base * len * if descending { 2.0 } else { 1.0 }
+        }),
+        (run.len() >= 3 && keyboard(run)).then_some(2.0 * 12.0 * len),
+        year(run).then_some(120.0),
+    ]
+    .into_iter()
+    .flatten()
+    .reduce(f64::min)
+}
+
+fn dictionary(run: &[char]) -> Option<f64> {
+    if run.len() < 3 {
+        return None;
+    }
+    let lower: String = run.iter().flat_map(|c| c.to_lowercase()).collect();
+    let unleet: String = lower
+        .chars()
+        .map(|c| match LEET.iter().find(|(l, _)| *l == c) {
+            Some((_, v)) => *v,
+            None => c,
+        })
+        .collect();
+
+    let (rank, leet) = match COMMON.iter().position(|v| *v == lower) {
+        Some(i) => (i, false),
+        None => (COMMON.iter().position(|v| *v == unleet)?, true),
+    };
+    let upper = run.iter().filter(|c| c.is_uppercase()).count();
+    let case = match upper {
+        0 => 1.0,
+        _ if upper == run.len() || (upper == 1 && run[0].is_uppercase()) => 2.0,
+        _ => 2f64.powi(upper as i32).min(100.0),
+    };
+    let leet = if leet { 2.0 } else { 1.0 };
+    Some((rank + 1) as f64 * case * leet)
+}
+
+fn repeat(run: &[char]) -> bool {
+    run.len() >= 3 && run.iter().all(|c| *c == run[0])
+}
+
+/// `Some(descending)` for runs like `abc`, `CBA` or `1234`.
+fn sequence(run: &[char]) -> Option<bool> {
+    if run.len() < 3 || !run.iter().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let delta = run[1] as i32 - run[0] as i32;
+    if delta.abs() != 1 {
+        return None;
+    }
+    run.windows(2)
+        .all(|w| w[1] as i32 - w[0] as i32 == delta)
+        .then_some(delta < 0)
+}
+
+/// Runs of neighbours on one row of a QWERTY keyboard, in either direction.
+fn keyboard(run: &[char]) -> bool {
+    let lower: Vec<char> = run.iter().map(|c| c.to_ascii_lowercase()).collect();
+    KEYBOARD_ROWS.iter().any(|row| {
+        let row: Vec<char> = row.chars().collect();
+        let pos = |c: &char| row.iter().position(|r| r == c).map(|v| v as i32);
+        let positions: Option<Vec<i32>> = lower.iter().map(pos).collect();
+        match positions {
+            Some(p) => {
+                let delta = p[1] - p[0];
+                delta.abs() == 1 && p.windows(2).all(|w| w[1] - w[0] == delta)
+            }
+            None => false,
+        }
+    })
+}
+
+fn year(run: &[char]) -> bool {
+    if run.len() != 4 || !run.iter().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let year: u32 = run.iter().collect::<String>().parse().unwrap_or_default();
+    (1900..2100).contains(&year)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_patterns_score_low() {
+        for v in [
+            "password",
+            "P@ssw0rd",
+            "qwerty123",
+            "aaaaaaaa",
+            "abcdef123",
+            "123456789",
+            "asdfgh",
+        ] {
+            assert_eq!(0, estimate(v).score, "{}", v);
+        }
+        assert!(estimate("Summer2024!").score <= 2);
+        assert!(estimate("").guesses_log10 == 0.0);
+    }
+
+    #[test]
+    fn test_random_passwords_score_high() {
+        let password = crate::password::generate(&Default::default()).expect("generating");
+        assert_eq!(4, estimate(&password).score);
+        assert!(estimate("k8#Vq2!xLp").score >= 3);
+        assert!(
+            estimate("correcthorsebatterystaple").guesses_log10
+                > estimate("password2024").guesses_log10
+        );
+    }
+}
@@ -0,0 +1,795 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+use crate::clipboard;
+use crate::command;
+use crate::file;
+use crate::kdf;
+use crate::otp;
+use crate::resource;
+use crate::secret::Secret;
+
+const HELP: &str = "/ search  c copy password  u copy user  o copy code  v reveal  \
+                    e edit  g generate  d delete  l lock  q quit";
+
+/// Replaces revealed secrets until `v` is pressed.
+const MASK: &str = "********";
+
+pub struct Settings {
+    /// Lock after this long without input, zero never locks.
+    pub lock_after: Duration,
+    pub clipboard_timeout: Duration,
+}
+
+/// Browse and change the file until the user quits. Every change goes
+/// through the same functions as the one-shot commands and is written
+/// right away.
+pub fn run(
+    custom_path: Option<&str>,
+    credential: kdf::Credential,
+    settings: Settings,
+) -> Result<(), String> {
+    let mut app = App::new(custom_path, credential, settings)?;
+    let mut terminal = ratatui::init();
+    let result = app.serve(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+enum Mode {
+    Browse,
+    /// Typing the query that narrows down the list.
+    Search,
+    /// Choosing which part of the selected resource to edit.
+    Choose,
+    /// Typing the name of the custom field to edit.
+    Field(String),
+    Edit {
+        key: resource::Key,
+        input: Secret,
+    },
+    Confirm(Action),
+    /// The credential is dropped, typing the master password unlocks again.
+    Locked {
+        input: Secret,
+        error: Option<String>,
+    },
+}
+
+/// Changes that ask for a y/n first.
+enum Action {
+    Delete(String),
+    Generate(String),
+}
+
+struct App {
+    custom_path: Option<String>,
+    credential: Option<kdf::Credential>,
+    settings: Settings,
+
+    resources: Vec<resource::Instance>,
+    /// `resources` matching `query`, best match first.
+    visible: Vec<resource::Instance>,
+    query: String,
+    selected: usize,
+    reveal: bool,
+
+    mode: Mode,
+    /// Result of the last action, shown until the next key.
+    status: Option<String>,
+    last_input: Instant,
+}
+
+impl App {
+    fn new(
+        custom_path: Option<&str>,
+        credential: kdf::Credential,
+        settings: Settings,
+    ) -> Result<App, String> {
+        let mut app = App {
+            custom_path: custom_path.map(|v| v.to_string()),
+            credential: Some(credential),
+            settings,
+            resources: vec![],
+            visible: vec![],
+            query: String::new(),
+            selected: 0,
+            reveal: false,
+            mode: Mode::Browse,
+            status: None,
+            last_input: Instant::now(),
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    fn serve(&mut self, terminal: &mut ratatui::DefaultTerminal) -> Result<(), String> {
+        loop {
+            if let Err(err) = terminal.draw(|frame| self.draw(frame)) {
+                return Err(err.to_string());
+            }
+
+            // Wake up in time to lock even if no key is pressed.
+            let wait = match self.lock_in(Instant::now()) {
+                Some(v) => v.max(Duration::from_millis(10)),
+                None => Duration::from_secs(60),
+            };
+            let ready = match event::poll(wait) {
+                Ok(v) => v,
+                Err(err) => return Err(err.to_string()),
+            };
+            if !ready {
+                if self.lock_in(Instant::now()) == Some(Duration::ZERO) {
+                    self.lock();
+                }
+                continue;
+            }
+            let key = match event::read() {
+                Ok(Event::Key(v)) if v.kind == KeyEventKind::Press => v,
+                Ok(_) => continue,
+                Err(err) => return Err(err.to_string()),
+            };
+            if self.lock_in(Instant::now()) == Some(Duration::ZERO) {
+                // The key that wakes up an idle session must not act on it,
+                // nor be typed into the master password.
+                self.lock();
+                continue;
+            }
+            if !self.key(key) {
+                return Ok(());
+            }
+            // After the key, unlocking may take a while.
+            self.last_input = Instant::now();
+        }
+    }
+
+    /// Time left until the session locks, `None` if it never does.
+    fn lock_in(&self, now: Instant) -> Option<Duration> {
+        if self.settings.lock_after.is_zero() || matches!(self.mode, Mode::Locked { .. }) {
+            return None;
+        }
+        let idle = now.saturating_duration_since(self.last_input);
+        Some(self.settings.lock_after.saturating_sub(idle))
+    }
+
+    /// Forget the credential and everything that was decrypted with it.
+    fn lock(&mut self) {
+        self.credential = None;
+        self.resources.clear();
+        self.visible.clear();
+        self.query.clear();
+        self.selected = 0;
+        self.reveal = false;
+        self.status = None;
+        self.mode = Mode::Locked {
+            input: Secret::default(),
+            error: None,
+        };
+    }
+
+    fn reload(&mut self) -> Result<(), String> {
+        let credential = match &self.credential {
+            Some(v) => v,
+            None => return Err("Locked".to_string()),
+        };
+        let content = file::decrypt(self.custom_path.as_deref(), credential)?;
+        self.resources = resource::list(content.expose(), &resource::Filter::default())?;
+        self.filter();
+        Ok(())
+    }
+
+    fn filter(&mut self) {
+        self.visible = resource::rank(self.resources.clone(), &self.query);
+        self.selected = self.selected.min(self.visible.len().saturating_sub(1));
+    }
+
+    fn selected(&self) -> Option<&resource::Instance> {
+        self.visible.get(self.selected)
+    }
+
+    /// Select another resource, hiding the secrets of the last one.
+    fn move_to(&mut self, i: usize) {
+        if i < self.visible.len() {
+            self.selected = i;
+            self.reveal = false;
+        }
+    }
+
+    fn select(&mut self, name: &str) {
+        if let Some(i) = self.visible.iter().position(|v| v.name == name) {
+            self.selected = i;
+        }
+    }
+
+    /// Handle a key press, `false` once the user quits.
+    fn key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        self.status = None;
+
+        let mode = std::mem::replace(&mut self.mode, Mode::Browse);
+        self.mode = match mode {
+            Mode::Browse => return self.browse(key),
+            Mode::Search => self.search(key),
+            Mode::Choose => self.choose(key),
+            Mode::Field(name) => self.field(key, name),
+            Mode::Edit { key: target, input } => self.edit(key, target, input),
+            Mode::Confirm(action) => self.confirm(key, action),
+            Mode::Locked { input, error } => match self.unlock(key, input, error) {
+                Some(v) => v,
+                None => return false,
+            },
+        };
+        true
+    }
+
+    fn browse(&mut self, key: KeyEvent) -> bool {
+        let name = self.selected().map(|v| v.name.clone());
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if self.query.is_empty() => return false,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.filter();
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_to(self.selected + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_to(self.selected.wrapping_sub(1)),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Char('l') => self.lock(),
+            KeyCode::Char('r') => {
+                if let Err(err) = self.reload() {
+                    self.status = Some(err);
+                }
+            }
+            _ => {}
+        }
+        let name = match name {
+            Some(v) => v,
+            None => return true,
+        };
+        match key.code {
+            KeyCode::Enter | KeyCode::Char('v') => self.reveal = !self.reveal,
+            KeyCode::Char('c') => self.copy_selected(resource::Key::Password),
+            KeyCode::Char('u') => self.copy_selected(resource::Key::User),
+            KeyCode::Char('o') => self.copy_selected(resource::Key::Otp),
+            KeyCode::Char('e') => self.mode = Mode::Choose,
+            KeyCode::Char('g') => self.mode = Mode::Confirm(Action::Generate(name)),
+            KeyCode::Char('d') => self.mode = Mode::Confirm(Action::Delete(name)),
+            _ => {}
+        }
+        true
+    }
+
+    fn search(&mut self, key: KeyEvent) -> Mode {
+        match key.code {
+            KeyCode::Enter => return Mode::Browse,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.filter();
+                return Mode::Browse;
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+            }
+            KeyCode::Char(c) => self.query.push(c),
+            _ => return Mode::Search,
+        }
+        self.selected = 0;
+        self.filter();
+        Mode::Search
+    }
+
+    fn choose(&mut self, key: KeyEvent) -> Mode {
+        let got = match self.selected() {
+            Some(v) => v,
+            None => return Mode::Browse,
+        };
+        let (key, val) = match key.code {
+            KeyCode::Char('n') => (resource::Key::Name, Secret::from(got.name.as_str())),
+            KeyCode::Char('u') => (resource::Key::User, Secret::from(got.user.as_str())),
+            KeyCode::Char('p') => (resource::Key::Password, Secret::default()),
+            KeyCode::Char('l') => (resource::Key::Url, Secret::from(got.url.as_str())),
+            KeyCode::Char('o') => (resource::Key::Notes, Secret::from(got.notes.as_str())),
+            KeyCode::Char('t') => (resource::Key::Tags, Secret::new(got.tags.join(", "))),
+            KeyCode::Char('s') => (resource::Key::Otp, Secret::default()),
+            KeyCode::Char('f') => return Mode::Field(String::new()),
+            _ => return Mode::Browse,
+        };
+        Mode::Edit { key, input: val }
+    }
+
+    fn field(&mut self, key: KeyEvent, mut name: String) -> Mode {
+        match key.code {
+            KeyCode::Esc => return Mode::Browse,
+            KeyCode::Enter if !name.trim().is_empty() => {
+                let name = name.trim().to_string();
+                let input = match self.selected().and_then(|v| v.fields.get(&name)) {
+                    Some(v) => v.clone(),
+                    None => Secret::default(),
+                };
+                return Mode::Edit {
+                    key: resource::Key::Field(name),
+                    input,
+                };
+            }
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Char(c) => name.push(c),
+            _ => {}
+        }
+        Mode::Field(name)
+    }
+
+    fn edit(&mut self, key: KeyEvent, target: resource::Key, mut input: Secret) -> Mode {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Mode::Browse,
+            KeyCode::Enter => {
+                self.update(target, input);
+                return Mode::Browse;
+            }
+            KeyCode::Char('g') if ctrl && matches!(target, resource::Key::Password) => {
+                if let Some(v) = self.generate() {
+                    input = v;
+                }
+            }
+            KeyCode::Backspace => {
+                input.expose_mut().pop();
+            }
+            KeyCode::Char(c) if !ctrl => input.expose_mut().push(c),
+            _ => {}
+        }
+        Mode::Edit { key: target, input }
+    }
+
+    fn confirm(&mut self, key: KeyEvent, action: Action) -> Mode {
+        if key.code != KeyCode::Char('y') {
+            return Mode::Browse;
+        }
+        match action {
+            Action::Generate(_) => {
+                if let Some(val) = self.generate() {
+                    self.update(resource::Key::Password, val);
+                }
+            }
+            Action::Delete(name) => {
+                let result = match &self.credential {
+                    Some(credential) => {
+                        command::delete_resource(self.custom_path.as_deref(), credential, &name)
+                    }
+                    None => return Mode::Browse,
+                };
+                self.status = Some(match result.and_then(|_| self.reload()) {
+                    Ok(_) => format!("Deleted {}", name),
+                    Err(err) => err,
+                });
+            }
+        }
+        Mode::Browse
+    }
+
+    fn unlock(&mut self, key: KeyEvent, mut input: Secret, error: Option<String>) -> Option<Mode> {
+        match key.code {
+            KeyCode::Esc => return None,
+            KeyCode::Enter => {
+                let credential = match file::unlock(self.custom_path.as_deref(), input.expose()) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        return Some(Mode::Locked {
+                            input: Secret::default(),
+                            error: Some(err),
+                        })
+                    }
+                };
+                self.credential = Some(credential);
+                if let Err(err) = self.reload() {
+                    self.status = Some(err);
+                }
+                return Some(Mode::Browse);
+            }
+            KeyCode::Backspace => {
+                input.expose_mut().pop();
+            }
+            KeyCode::Char(c) => input.expose_mut().push(c),
+            _ => {}
+        }
+        Some(Mode::Locked { input, error })
+    }
+
+    fn update(&mut self, key: resource::Key, val: Secret) {
+        let (credential, got) = match (&self.credential, self.selected()) {
+            (Some(c), Some(v)) => (c, v),
+            _ => return,
+        };
+        let name = match &key {
+            resource::Key::Name => val.expose().to_string(),
+            _ => got.name.clone(),
+        };
+        let label = label(&key);
+        let result = command::update_resource(
+            self.custom_path.as_deref(),
+            credential,
+            got.name.clone(),
+            key,
+            val,
+        );
+        self.status = Some(match result.and_then(|_| self.reload()) {
+            Ok(_) => {
+                self.select(&name);
+                format!("Updated the {} of {}", label, name)
+            }
+            Err(err) => err,
+        });
+    }
+
+    /// A new password for the selected resource, made the way it wants.
+    fn generate(&mut self) -> Option<Secret> {
+        let generator = match self.selected() {
+            Some(v) => v.generator.clone().unwrap_or_default(),
+            None => return None,
+        };
+        match generator.generate() {
+            Ok(v) => Some(Secret::new(v)),
+            Err(err) => {
+                self.status = Some(err);
+                None
+            }
+        }
+    }
+
+    fn copy_selected(&mut self, key: resource::Key) {
+        let got = match self.selected() {
+            Some(v) => v.clone(),
+            None => return,
+        };
+        let label = match key {
+            resource::Key::Otp => "totp secret".to_string(),
+            _ => label(&key),
+        };
+        let value = match key {
+            resource::Key::User => Secret::from(got.user.as_str()),
+            resource::Key::Otp if got.otp.is_empty() => {
+                self.status = Some(format!("{} has no one-time password secret", got.name));
+                return;
+            }
+            resource::Key::Otp => match otp::parse(got.otp.expose()) {
+                Ok(v) => Secret::new(v.now().0),
+                Err(err) => {
+                    self.status = Some(err);
+                    return;
+                }
+            },
+            _ => got.password.clone(),
+        };
+        let timeout = self.settings.clipboard_timeout;
+        self.status = Some(match clipboard::copy(value.expose(), timeout) {
+            Ok(_) if timeout.is_zero() => format!("Copied the {}", label),
+            Ok(_) => format!(
+                "Copied the {}, clearing the clipboard in {}s",
+                label,
+                timeout.as_secs()
+            ),
+            Err(err) => format!("Could not copy the {}: {}", label, err),
+        });
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        if let Mode::Locked { input, error } = &self.mode {
+            draw_locked(frame, input, error.as_deref());
+            return;
+        }
+
+        let [main, bar] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, details] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+
+        let title = match (&self.mode, self.query.is_empty()) {
+            (Mode::Search, _) | (_, false) => format!(" /{} ", self.query),
+            _ => format!(" Resources ({}) ", self.resources.len()),
+        };
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|v| {
+                let mut spans = vec![Span::raw(v.name.clone())];
+                if !v.tags.is_empty() {
+                    spans.push(Span::raw(format!(" [{}]", v.tags.join(", "))).dim());
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::new().reversed()),
+            list,
+            &mut state,
+        );
+
+        let lines = match self.selected() {
+            Some(v) => details_lines(v, self.reveal),
+            None if self.query.is_empty() => vec![Line::raw("No resources")],
+            None => vec![Line::raw("No matches")],
+        };
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::bordered().title(" Details "))
+                .wrap(Wrap { trim: false }),
+            details,
+        );
+
+        self.draw_bar(frame, bar);
+    }
+
+    fn draw_bar(&self, frame: &mut Frame, area: Rect) {
+        let (text, input) = match &self.mode {
+            Mode::Search => (format!("/{}", self.query), true),
+            Mode::Choose => (
+                "Edit name (n), user (u), password (p), url (l), notes (o), tags (t), \
+                 totp secret (s) or a custom field (f)?"
+                    .to_string(),
+                false,
+            ),
+            Mode::Field(name) => (format!("Custom field name: {}", name), true),
+            Mode::Edit { key, input } => {
+                let shown = match key {
+                    resource::Key::Password | resource::Key::Otp => {
+                        "*".repeat(input.expose().chars().count())
+                    }
+                    _ => input.expose().to_string(),
+                };
+                let hint = match key {
+                    resource::Key::Password => " (Ctrl-G generates one)",
+                    _ => "",
+                };
+                (format!("New {}{}: {}", label(key), hint, shown), true)
+            }
+            Mode::Confirm(Action::Delete(name)) => (format!("Delete {}? (y/n)", name), false),
+            Mode::Confirm(Action::Generate(name)) => (
+                format!(
+                    "Replace the password of {} with a generated one? (y/n)",
+                    name
+                ),
+                false,
+            ),
+            _ => match &self.status {
+                Some(v) => (v.clone(), false),
+                None => (HELP.to_string(), false),
+            },
+        };
+        let width = text.chars().count() as u16;
+        frame.render_widget(Paragraph::new(text), area);
+        if input {
+            frame.set_cursor_position(Position::new(area.x + width, area.y));
+        }
+    }
+}
+
+fn draw_locked(frame: &mut Frame, input: &Secret, error: Option<&str>) {
+    let [_, area, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(5),
+        Constraint::Fill(1),
+    ])
+    .areas(frame.area());
+    let prompt = format!(
+        "master password: {}",
+        "*".repeat(input.expose().chars().count())
+    );
+    let width = prompt.chars().count() as u16;
+    let mut lines = vec![Line::raw(prompt)];
+    if let Some(v) = error {
+        lines.push(Line::raw(v.to_string()).red());
+    }
+    lines.push(Line::raw("Esc quits").dim());
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Locked ")),
+        area,
+    );
+    frame.set_cursor_position(Position::new(area.x + 1 + width, area.y + 1));
+}
+
+fn details_lines(got: &resource::Instance, reveal: bool) -> Vec<Line<'static>> {
+    let secret = |v: &Secret| -> String {
+        if reveal {
+            v.expose().to_string()
+        } else {
+            MASK.to_string()
+        }
+    };
+    let mut lines = vec![
+        Line::from(got.name.clone()).bold(),
+        Line::raw(format!("Username: {}", got.user)),
+        Line::raw(format!("Password: {}", secret(&got.password))),
+    ];
+    if !got.url.is_empty() {
+        lines.push(Line::raw(format!("URL: {}", got.url)));
+    }
+    if !got.tags.is_empty() {
+        lines.push(Line::raw(format!("Tags: {}", got.tags.join(", "))));
+    }
+    if !got.otp.is_empty() {
+        lines.push(Line::raw("One-time codes: press o to copy one"));
+    }
+    for (k, v) in &got.fields {
+        lines.push(Line::raw(format!("{}: {}", k, secret(v))));
+    }
+    if !got.notes.is_empty() {
+        lines.push(Line::raw(""));
+        lines.push(Line::raw("Notes:"));
+        for v in got.notes.lines() {
+            lines.push(Line::raw(v.to_string()));
+        }
+    }
+    lines
+}
+
+fn label(key: &resource::Key) -> String {
+    match key {
+        resource::Key::Name => "name".to_string(),
+        resource::Key::User => "username".to_string(),
+        resource::Key::Password => "password".to_string(),
+        resource::Key::Url => "url".to_string(),
+        resource::Key::Notes => "notes".to_string(),
+        resource::Key::Tags => "tags".to_string(),
+        resource::Key::Otp => "totp secret".to_string(),
+        resource::Key::Field(v) => v.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::password;
+
+    fn app(names: &[&str]) -> App {
+        let resources: Vec<resource::Instance> = names
+            .iter()
+            .map(|v| resource::Instance {
+                name: v.to_string(),
+                user: format!("{}-user", v),
+                ..Default::default()
+            })
+            .collect();
+        let mut app = App {
+            custom_path: None,
+            credential: None,
+            settings: Settings {
+                lock_after: Duration::from_secs(60),
+                clipboard_timeout: Duration::ZERO,
+            },
+            resources,
+            visible: vec![],
+            query: String::new(),
+            selected: 0,
+            reveal: false,
+            mode: Mode::Browse,
+            status: None,
+            last_input: Instant::now(),
+        };
+        app.filter();
+        app
+    }
+
+    fn press(app: &mut App, keys: &str) -> bool {
+        keys.chars()
+            .all(|c| app.key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)))
+    }
+
+    fn press_code(app: &mut App, code: KeyCode) -> bool {
+        app.key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn names(app: &App) -> Vec<&str> {
+        app.visible.iter().map(|v| v.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_browse_and_search() {
+        let mut app = app(&["github", "mail", "gitlab"]);
+        assert_eq!(vec!["github", "mail", "gitlab"], names(&app));
+
+        press(&mut app, "jjj");
+        assert_eq!(Some("gitlab"), app.selected().map(|v| v.name.as_str()));
+        press(&mut app, "v");
+        assert!(app.reveal);
+        press(&mut app, "k");
+        assert!(!app.reveal);
+
+        press(&mut app, "/git");
+        assert!(matches!(app.mode, Mode::Search));
+        assert_eq!(vec!["github", "gitlab"], names(&app));
+        press_code(&mut app, KeyCode::Enter);
+        assert!(matches!(app.mode, Mode::Browse));
+        assert_eq!("git", app.query);
+
+        // Esc clears the search first and quits after.
+        assert!(press_code(&mut app, KeyCode::Esc));
+        assert_eq!(3, app.visible.len());
+        assert!(!press_code(&mut app, KeyCode::Esc));
+    }
+
+    #[test]
+    fn test_edit_and_confirm() {
+        let mut app = app(&["github"]);
+
+        press(&mut app, "eu");
+        match &app.mode {
+            Mode::Edit { key, input } => {
+                assert!(matches!(key, resource::Key::User));
+                assert_eq!("github-user", input.expose());
+            }
+            _ => panic!("expected edit"),
+        }
+        press_code(&mut app, KeyCode::Esc);
+        assert!(matches!(app.mode, Mode::Browse));
+
+        press(&mut app, "efapi");
+        assert!(matches!(&app.mode, Mode::Field(v) if v == "api"));
+        press_code(&mut app, KeyCode::Enter);
+        assert!(matches!(&app.mode, Mode::Edit { key: resource::Key::Field(v), .. } if v == "api"));
+        press_code(&mut app, KeyCode::Esc);
+
+        press(&mut app, "d");
+        assert!(matches!(&app.mode, Mode::Confirm(Action::Delete(v)) if v == "github"));
+        press(&mut app, "n");
+        assert!(matches!(app.mode, Mode::Browse));
+        assert_eq!(1, app.resources.len());
+    }
+
+    #[test]
+    fn test_generate_uses_resource_policy() {
+        let mut app = app(&["vendor"]);
+        let policy = password::Policy {
+            length: 24,
+            symbols: String::new(),
+            ..Default::default()
+        };
+        app.visible[0].generator = Some(password::Generator::Password(policy));
+
+        press(&mut app, "ep");
+        app.key(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL));
+        match &app.mode {
+            Mode::Edit { input, .. } => {
+                assert_eq!(24, input.expose().len());
+                assert!(input.expose().chars().all(|c| c.is_ascii_alphanumeric()));
+            }
+            _ => panic!("expected edit"),
+        }
+    }
+
+    #[test]
+    fn test_lock() {
+        let mut app = app(&["github"]);
+        let now = app.last_input;
+        assert_eq!(Some(Duration::from_secs(60)), app.lock_in(now));
+        assert_eq!(
+            Some(Duration::ZERO),
+            app.lock_in(now + Duration::from_secs(61))
+        );
+
+        press(&mut app, "/git");
+        press_code(&mut app, KeyCode::Enter);
+        press(&mut app, "l");
+        assert!(matches!(app.mode, Mode::Locked { .. }));
+        assert!(app.resources.is_empty() && app.visible.is_empty());
+        assert!(app.query.is_empty());
+        assert_eq!(None, app.lock_in(now + Duration::from_secs(61)));
+
+        press(&mut app, "pw");
+        assert!(matches!(&app.mode, Mode::Locked { input, .. } if input.expose() == "pw"));
+        assert!(!press_code(&mut app, KeyCode::Esc));
+    }
+}